@@ -0,0 +1,107 @@
+//! Minimal WebDriver BiDi session client
+
+use crate::websocket::WebSocket;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A connection to a WebDriver BiDi endpoint
+pub struct BidiSession {
+    socket: WebSocket,
+    next_id: u64,
+    session_id: Option<String>,
+    /// Events received while waiting for a command response
+    events: VecDeque<Value>,
+}
+
+impl BidiSession {
+    /// Connect to a BiDi websocket URL such as `ws://127.0.0.1:9222/session`
+    pub fn connect(ws_url: &str) -> Result<Self, String> {
+        Ok(Self {
+            socket: WebSocket::connect(ws_url, COMMAND_TIMEOUT)?,
+            next_id: 1,
+            session_id: None,
+            events: VecDeque::new(),
+        })
+    }
+
+    /// Send a command and wait for its result
+    pub fn send_command(&mut self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.socket
+            .send_text(&json!({ "id": id, "method": method, "params": params }).to_string())?;
+
+        loop {
+            let message = self.read_message()?;
+            match message["type"].as_str() {
+                Some("event") => self.events.push_back(message),
+                _ if message["id"].as_u64() != Some(id) => {}
+                Some("error") => {
+                    return Err(format!(
+                        "{} failed: {}: {}",
+                        method,
+                        message["error"].as_str().unwrap_or("unknown error"),
+                        message["message"].as_str().unwrap_or_default()
+                    ))
+                }
+                _ => return Ok(message["result"].clone()),
+            }
+        }
+    }
+
+    /// Query `session.status`; works before a session is created
+    pub fn status(&mut self) -> Result<Value, String> {
+        self.send_command("session.status", json!({}))
+    }
+
+    /// Create a session with `session.new` and return its id
+    pub fn new_session(&mut self, capabilities: Value) -> Result<String, String> {
+        let result = self.send_command("session.new", json!({ "capabilities": capabilities }))?;
+        let session_id = result["sessionId"]
+            .as_str()
+            .ok_or_else(|| "session.new returned no sessionId".to_string())?
+            .to_string();
+        self.session_id = Some(session_id.clone());
+        Ok(session_id)
+    }
+
+    /// The id of the session created by `new_session`
+    pub fn session_id(&self) -> Option<&str> {
+        self.session_id.as_deref()
+    }
+
+    /// Subscribe to events such as `log.entryAdded`
+    pub fn subscribe(&mut self, events: &[&str]) -> Result<(), String> {
+        self.send_command("session.subscribe", json!({ "events": events }))
+            .map(|_| ())
+    }
+
+    /// Wait for the next event
+    pub fn next_event(&mut self) -> Result<Value, String> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(event);
+        }
+        loop {
+            let message = self.read_message()?;
+            if message["type"] == "event" {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// End the session (if one was created) and close the connection
+    pub fn end(mut self) -> Result<(), String> {
+        if self.session_id.is_some() {
+            self.send_command("session.end", json!({}))?;
+        }
+        self.socket.close()
+    }
+
+    fn read_message(&mut self) -> Result<Value, String> {
+        let text = self.socket.read_text()?;
+        serde_json::from_str(&text).map_err(|e| format!("Invalid BiDi message: {}", e))
+    }
+}
@@ -3,8 +3,9 @@ use home::home_dir;
 use std::env;
 use std::path::Path;
 use std::process::Command;
+use std::str::FromStr;
 
-/// Represents different types of Chromium-based browsers
+/// Represents different types of Chromium-based and Firefox browsers
 #[derive(Debug, Clone, PartialEq)]
 pub enum BrowserType {
     Chrome,
@@ -14,6 +15,10 @@ pub enum BrowserType {
     Brave,
     Opera,
     Vivaldi,
+    Firefox,
+    FirefoxEsr,
+    FirefoxNightly,
+    FirefoxDeveloperEdition,
     Custom(String),
 }
 
@@ -28,10 +33,34 @@ impl BrowserType {
             BrowserType::Brave => "Brave",
             BrowserType::Opera => "Opera",
             BrowserType::Vivaldi => "Vivaldi",
+            BrowserType::Firefox => "Mozilla Firefox",
+            BrowserType::FirefoxEsr => "Mozilla Firefox ESR",
+            BrowserType::FirefoxNightly => "Firefox Nightly",
+            BrowserType::FirefoxDeveloperEdition => "Firefox Developer Edition",
             BrowserType::Custom(name) => name,
         }
     }
 
+    /// Whether this is a Firefox (Gecko) browser rather than a Chromium-based one
+    pub fn is_firefox(&self) -> bool {
+        matches!(
+            self,
+            BrowserType::Firefox
+                | BrowserType::FirefoxEsr
+                | BrowserType::FirefoxNightly
+                | BrowserType::FirefoxDeveloperEdition
+        )
+    }
+
+    /// The remote protocol the browser serves on its own
+    pub fn default_protocol(&self) -> Protocol {
+        if self.is_firefox() {
+            Protocol::Bidi
+        } else {
+            Protocol::Cdp
+        }
+    }
+
     /// Get the executable names for this browser type
     pub fn executables(&self) -> Vec<&str> {
         match self {
@@ -42,10 +71,23 @@ impl BrowserType {
             BrowserType::Brave => vec!["brave-browser-stable", "brave-browser", "brave"],
             BrowserType::Opera => vec!["opera-stable", "opera"],
             BrowserType::Vivaldi => vec!["vivaldi-stable", "vivaldi"],
+            BrowserType::Firefox => vec!["firefox"],
+            BrowserType::FirefoxEsr => vec!["firefox-esr"],
+            BrowserType::FirefoxNightly => vec!["firefox-nightly"],
+            BrowserType::FirefoxDeveloperEdition => vec!["firefox-developer-edition", "firefox-dev"],
             BrowserType::Custom(executable) => vec![executable],
         }
     }
 
+    /// Get the WebDriver executable names for this browser type
+    pub fn driver_executables(&self) -> Vec<&str> {
+        match self {
+            BrowserType::Edge => vec!["msedgedriver"],
+            _ if self.is_firefox() => vec![],
+            _ => vec!["chromedriver"],
+        }
+    }
+
     /// Get macOS application paths for this browser type
     pub fn macos_app_paths(&self) -> Vec<&str> {
         match self {
@@ -68,6 +110,15 @@ impl BrowserType {
             BrowserType::Vivaldi => vec![
                 "/Applications/Vivaldi.app/Contents/MacOS/Vivaldi",
             ],
+            BrowserType::Firefox | BrowserType::FirefoxEsr => vec![
+                "/Applications/Firefox.app/Contents/MacOS/firefox",
+            ],
+            BrowserType::FirefoxNightly => vec![
+                "/Applications/Firefox Nightly.app/Contents/MacOS/firefox",
+            ],
+            BrowserType::FirefoxDeveloperEdition => vec![
+                "/Applications/Firefox Developer Edition.app/Contents/MacOS/firefox",
+            ],
             _ => vec![],
         }
     }
@@ -97,6 +148,65 @@ impl BrowserType {
     }
 }
 
+impl FromStr for BrowserType {
+    type Err = String;
+
+    /// Parse a browser name such as `chrome` or `edge`. Values that look like a
+    /// path (or carry a `custom:` prefix) become `BrowserType::Custom`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim();
+        if let Some(path) = value.strip_prefix("custom:") {
+            return Ok(BrowserType::Custom(path.to_string()));
+        }
+        match value.to_ascii_lowercase().as_str() {
+            "chrome" => Ok(BrowserType::Chrome),
+            "chrome-canary" | "canary" => Ok(BrowserType::ChromeCanary),
+            "chromium" => Ok(BrowserType::Chromium),
+            "edge" => Ok(BrowserType::Edge),
+            "brave" => Ok(BrowserType::Brave),
+            "opera" => Ok(BrowserType::Opera),
+            "vivaldi" => Ok(BrowserType::Vivaldi),
+            "firefox" => Ok(BrowserType::Firefox),
+            "firefox-esr" => Ok(BrowserType::FirefoxEsr),
+            "firefox-nightly" => Ok(BrowserType::FirefoxNightly),
+            "firefox-developer-edition" | "firefox-dev" => Ok(BrowserType::FirefoxDeveloperEdition),
+            _ if value.contains('/') || value.contains('\\') => Ok(BrowserType::Custom(value.to_string())),
+            _ => Err(format!("Unknown browser type: {}", value)),
+        }
+    }
+}
+
+/// Remote automation protocol a launched browser is expected to serve
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    /// Chrome DevTools Protocol
+    Cdp,
+    /// WebDriver BiDi
+    Bidi,
+}
+
+impl Protocol {
+    /// Get the short name used on the command line and in JSON output
+    pub fn name(&self) -> &str {
+        match self {
+            Protocol::Cdp => "cdp",
+            Protocol::Bidi => "bidi",
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "cdp" => Ok(Protocol::Cdp),
+            "bidi" | "webdriver-bidi" => Ok(Protocol::Bidi),
+            other => Err(format!("Unknown protocol: {}", other)),
+        }
+    }
+}
+
 /// Represents a found browser installation
 #[derive(Debug, Clone)]
 pub struct Browser {
@@ -143,12 +253,12 @@ impl Browser {
             .output()
         {
             if let Ok(version_str) = String::from_utf8(output.stdout) {
-                // Parse version from output (usually format: "BrowserName Version.X.X.X")
+                // Parse version from output (e.g. "Google Chrome 120.0.6099.109" or "Mozilla Firefox 128.0")
                 let version_line = version_str.lines().next()?;
-                let parts: Vec<&str> = version_line.split_whitespace().collect();
-                if parts.len() >= 2 {
-                    return Some(parts[1].to_string());
-                }
+                return version_line
+                    .split_whitespace()
+                    .find(|part| part.starts_with(|c: char| c.is_ascii_digit()))
+                    .map(String::from);
             }
         }
         None
@@ -183,6 +293,19 @@ impl BrowserFinder {
 }
 
 impl BrowserFinder {
+    /// Create a BrowserFinder that looks for every supported browser,
+    /// Chromium-based ones first, then the Firefox channels
+    pub fn all_browsers() -> Self {
+        let mut preferred_browsers = Self::default().preferred_browsers;
+        preferred_browsers.extend([
+            BrowserType::Firefox,
+            BrowserType::FirefoxEsr,
+            BrowserType::FirefoxNightly,
+            BrowserType::FirefoxDeveloperEdition,
+        ]);
+        Self { preferred_browsers }
+    }
+
     /// Create a new BrowserFinder with custom preferences
     pub fn new(preferred_browsers: Vec<BrowserType>) -> Self {
         Self { preferred_browsers }
@@ -235,7 +358,7 @@ impl BrowserFinder {
     #[cfg(target_os = "macos")]
     fn find_on_macos(&self, browser_type: &BrowserType) -> Option<Browser> {
         // Check environment variables first
-        if let Some(path) = self.check_env_vars() {
+        if let Some(path) = self.check_env_vars(browser_type) {
             return Some(Browser::new(browser_type.clone(), path));
         }
 
@@ -254,7 +377,7 @@ impl BrowserFinder {
     #[cfg(target_os = "windows")]
     fn find_on_windows(&self, browser_type: &BrowserType) -> Option<Browser> {
         // Check environment variables first
-        if let Some(path) = self.check_env_vars() {
+        if let Some(path) = self.check_env_vars(browser_type) {
             return Some(Browser::new(browser_type.clone(), path));
         }
 
@@ -274,7 +397,7 @@ impl BrowserFinder {
     #[cfg(target_os = "linux")]
     fn find_on_linux(&self, browser_type: &BrowserType) -> Option<Browser> {
         // Check environment variables first
-        if let Some(path) = self.check_env_vars() {
+        if let Some(path) = self.check_env_vars(browser_type) {
             return Some(Browser::new(browser_type.clone(), path));
         }
 
@@ -296,9 +419,13 @@ impl BrowserFinder {
         self.find_in_linux_paths(browser_type)
     }
 
-    fn check_env_vars(&self) -> Option<String> {
-        // Check common environment variables
-        let env_vars = vec!["CHROME_PATH", "LIGHTHOUSE_CHROMIUM_PATH", "BROWSER_PATH"];
+    fn check_env_vars(&self, browser_type: &BrowserType) -> Option<String> {
+        // Check common environment variables; the Chrome ones never point at Firefox
+        let env_vars = if browser_type.is_firefox() {
+            vec!["FIREFOX_PATH", "BROWSER_PATH"]
+        } else {
+            vec!["CHROME_PATH", "LIGHTHOUSE_CHROMIUM_PATH", "BROWSER_PATH"]
+        };
 
         for var in env_vars {
             if let Ok(path) = env::var(var) {
@@ -387,6 +514,15 @@ impl BrowserFinder {
             BrowserType::Vivaldi => vec![
                 "Vivaldi\\Application\\vivaldi.exe",
             ],
+            BrowserType::Firefox | BrowserType::FirefoxEsr => vec![
+                "Mozilla Firefox\\firefox.exe",
+            ],
+            BrowserType::FirefoxNightly => vec![
+                "Firefox Nightly\\firefox.exe",
+            ],
+            BrowserType::FirefoxDeveloperEdition => vec![
+                "Firefox Developer Edition\\firefox.exe",
+            ],
             _ => vec![],
         };
 
@@ -419,6 +555,12 @@ impl BrowserFinder {
             "/opt/brave.com/brave",
             "/opt/opera",
             "/opt/vivaldi",
+            "/usr/lib/firefox",
+            "/usr/lib/firefox-esr",
+            "/opt/firefox",
+            "/opt/firefox-nightly",
+            "/opt/firefox-developer-edition",
+            "/snap/bin",
         ];
 
         for base_path in common_paths {
@@ -1,52 +1,223 @@
-use crate::browser::{BrowserFinder, BrowserType};
-use crate::flags::DEFAULT_FLAGS;
+use crate::bidi::BidiSession;
+use crate::browser::{BrowserFinder, BrowserType, Protocol};
+use crate::certs;
+use crate::cgroup::{Cgroup, ResourceLimits, ResourceUsage};
+use crate::config;
+use crate::devtools;
+use crate::devices::DevicePreset;
+use crate::display::{detect_ozone_platform, OzonePlatform, VirtualDisplay, DEFAULT_SCREEN_SIZE};
+use crate::flags::{DEFAULT_FIREFOX_PREFS, DEFAULT_FLAGS};
+use crate::extensions::Extension;
+use crate::forwarder::ProxyForwarder;
+use crate::host::{HostEnvironment, SandboxPolicy};
+use crate::locale;
+use crate::native_messaging::{InstalledNativeHost, NativeMessagingHost};
+use crate::sched::ProcessControls;
+use crate::proxy::{ProxyConfig, ProxyServer};
+use crate::resolver::HostResolverRules;
+use crate::plan::{FlagSource, LaunchPlan, PlannedArg, PlannedEnvVar, ProfileAction};
 use std::path::Path;
 use crate::utils::get_default;
+use crate::wrapper::{self, SandboxWrapper};
 use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::fs::File;
 use std::path::PathBuf;
 use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long `LaunchedBrowser::kill` lets a wrapper exit after its browser died
+const WRAPPER_EXIT_POLLS: u32 = 20;
+const WRAPPER_EXIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 #[derive(Default)]
 #[derive(Debug)]
 pub struct Options {
     pub starting_url: Option<String>,
+    /// Open several URLs, one tab each, instead of `starting_url`. In app and
+    /// kiosk mode the extra URLs are opened over DevTools once the browser is ready.
+    pub starting_urls: Option<Vec<String>>,
     pub browser_flags: Option<Vec<String>>,
     pub prefs: Option<HashMap<String, serde_json::Value>>,
     pub port: Option<u16>,
+    /// Shut the browser down on SIGINT. Honoured by the CLI; library callers
+    /// read it back with `Launcher::handle_sigint` and install their own handler.
     pub handle_sigint: Option<bool>,
     pub browser_path: Option<String>,
     pub user_data_dir: Option<String>,
+    /// Browser logging to its stderr log: verbose, info, warning, error or silent
     pub log_level: Option<String>,
     pub ignore_default_flags: Option<bool>,
     pub connection_poll_interval: Option<u64>,
     pub max_connection_retries: Option<u32>,
     pub env_vars: Option<HashMap<String, String>>,
     pub browser: Option<BrowserType>,
+    /// Protocol to wait for during readiness; defaults to the browser's own.
+    /// Chromium-based browsers serve BiDi only through chromedriver, so `Bidi`
+    /// is rejected for them; use `DriverLauncher` and the `webSocketUrl` capability.
+    pub protocol: Option<Protocol>,
     pub headless: Option<bool>,
     pub window_size: Option<(u32, u32)>,
+    /// Bundled device whose user agent, window size, scale factor and touch
+    /// support are used; explicit `user_agent` and `window_size` win over it
+    pub device: Option<DevicePreset>,
+    /// Top-left corner of the window; may be negative on multi-monitor setups
+    pub window_position: Option<(i32, i32)>,
+    pub start_maximized: Option<bool>,
+    pub fullscreen: Option<bool>,
+    /// Fullscreen without browser UI or a way to leave it
+    pub kiosk: Option<bool>,
+    /// Open this URL in an app window without tabs or address bar, instead of `starting_url`
+    pub app_url: Option<String>,
+    /// Run a headful browser on an Xvfb display started for it (ignored when headless)
+    pub virtual_display: Option<bool>,
+    /// Xvfb screen size; defaults to `window_size`
+    pub virtual_display_size: Option<(u32, u32)>,
+    /// Ozone platform for Chromium on Linux; detected from the session when unset
+    pub ozone_platform: Option<OzonePlatform>,
+    /// Run the browser tree in its own cgroup v2 with these limits (Linux).
+    /// When the controllers are not yet enabled, the launcher moves itself into a
+    /// `launcher` leaf of its cgroup, and fails if that cgroup holds other processes.
+    pub resource_limits: Option<ResourceLimits>,
+    /// Scheduling priority of the browser, -20 to 19 (Unix)
+    pub nice: Option<i32>,
+    /// CPUs the browser may run on (Linux)
+    pub cpu_affinity: Option<Vec<usize>>,
+    /// OOM killer adjustment, -1000 to 1000; positive values make the browser go first (Linux)
+    pub oom_score_adj: Option<i32>,
     pub incognito: Option<bool>,
     pub disable_gpu: Option<bool>,
     pub no_sandbox: Option<bool>,
+    /// Reaction to root, container and small `/dev/shm` hosts; defaults to auto
+    pub sandbox_policy: Option<SandboxPolicy>,
+    /// Launch the browser inside bwrap or behind a prefix command such as `firejail`
+    pub sandbox_wrapper: Option<SandboxWrapper>,
+    /// Command prefix such as `strace -f` or `taskset -c 0`, put before the
+    /// browser and any sandbox wrapper
+    pub wrapper: Option<Vec<String>>,
     pub disable_web_security: Option<bool>,
     pub allow_running_insecure_content: Option<bool>,
     pub ignore_ssl_errors: Option<bool>,
+    /// PEM certificates whose public keys Chromium accepts despite certificate
+    /// errors, without ignoring errors for every other site
+    pub trusted_certificates: Option<Vec<PathBuf>>,
     pub disable_extensions: Option<bool>,
+    /// Unpacked extension directories to load; replaces `--disable-extensions`
+    /// with `--disable-extensions-except` for them (Chromium only)
+    pub extensions: Option<Vec<PathBuf>>,
+    /// Native messaging host manifests written before launch and removed on shutdown
+    pub native_messaging_hosts: Option<Vec<NativeMessagingHost>>,
     pub disable_plugins: Option<bool>,
     pub disable_images: Option<bool>,
     pub disable_javascript: Option<bool>,
     pub user_agent: Option<String>,
+    /// BCP 47 language tag such as `de-DE`, for the UI and `Accept-Language`
+    pub locale: Option<String>,
+    /// IANA timezone such as `America/New_York`, set through `TZ`
+    pub timezone: Option<String>,
+    /// Shorthand for `proxy` with manual rules, e.g. `socks5://proxy:1080`
     pub proxy_server: Option<String>,
-    pub host_resolver_rules: Option<String>,
+    /// Proxy servers, PAC script, direct or system settings. An http upstream
+    /// with `user:password@` is reached through a local forwarder on loopback,
+    /// which outside Linux any local user can connect to and use the credentials.
+    pub proxy: Option<ProxyConfig>,
+    /// `--host-resolver-rules`; parse a raw rule string with `str::parse`
+    pub host_resolver_rules: Option<HostResolverRules>,
     pub additional_args: Option<Vec<String>>,
 }
 
 pub struct LaunchedBrowser {
+    /// PID of the browser itself, also when it runs under a wrapper
     pub pid: u32,
+    /// PID of the wrapper command `process` runs, unless the wrapper exec'd the browser
+    pub wrapper_pid: Option<u32>,
+    /// Remote debugging port; updated to the real port by `Launcher::wait_until_ready`
     pub port: u16,
     pub process: std::process::Child,
+    pub user_data_dir: String,
+    /// Browser-level websocket URL for `protocol`, known once the browser is ready
+    pub ws_url: Option<String>,
+    /// Protocol spoken on `ws_url`
+    pub protocol: Protocol,
+    /// Xvfb server the browser draws on; stopped together with the browser
+    pub virtual_display: Option<VirtualDisplay>,
+    /// cgroup holding the browser tree; killed and removed together with the browser
+    pub cgroup: Option<Cgroup>,
+    /// Local proxy adding upstream credentials; stopped together with the browser
+    pub proxy_forwarder: Option<ProxyForwarder>,
+    /// Loaded unpacked extensions and their IDs
+    pub extensions: Vec<Extension>,
+    /// Installed native messaging host manifests; removed together with the browser
+    pub native_messaging_hosts: Vec<InstalledNativeHost>,
+    /// Problems that did not stop the launch, for the caller to report
+    pub warnings: Vec<String>,
+    /// URLs `wait_until_ready` opens over DevTools
+    pending_urls: Vec<String>,
+}
+
+impl LaunchedBrowser {
+    /// Wait for the browser to exit, then stop its virtual display and
+    /// remove its cgroup along with any leftover children
+    pub fn wait(&mut self) -> std::io::Result<std::process::ExitStatus> {
+        let status = self.process.wait();
+        self.teardown();
+        status
+    }
+
+    /// Kill the browser (the whole tree when it has a cgroup) and release its resources
+    pub fn kill(&mut self) -> std::io::Result<()> {
+        let cgroup_killed = match self.cgroup {
+            Some(ref cgroup) => cgroup.kill(),
+            None => Ok(()),
+        };
+        let browser_killed = match self.wrapper_pid {
+            Some(_) => {
+                // Signal the browser itself, then give the wrapper a moment to reap
+                // it and exit on its own before killing the wrapper too
+                let killed = kill_browser(self.pid);
+                for _ in 0..WRAPPER_EXIT_POLLS {
+                    if let Ok(Some(_)) = self.process.try_wait() {
+                        break;
+                    }
+                    thread::sleep(WRAPPER_EXIT_POLL_INTERVAL);
+                }
+                killed
+            }
+            None => Ok(()),
+        };
+        let killed = self.process.kill();
+        let _ = self.process.wait();
+        self.teardown();
+        killed?;
+        browser_killed?;
+        cgroup_killed.map_err(std::io::Error::other)
+    }
+
+    /// Usage counters of the browser tree, when it runs in its own cgroup
+    pub fn resource_usage(&self) -> Option<ResourceUsage> {
+        self.cgroup.as_ref().map(Cgroup::usage)
+    }
+
+    fn teardown(&mut self) {
+        self.cgroup = None;
+        self.virtual_display = None;
+        self.proxy_forwarder = None;
+        self.native_messaging_hosts.clear();
+    }
+
+    /// Open a WebDriver BiDi connection to the browser
+    pub fn connect_bidi(&self) -> Result<BidiSession, String> {
+        if self.protocol != Protocol::Bidi {
+            return Err(format!("The browser was launched for {}, not bidi", self.protocol.name()));
+        }
+        let ws_url = self
+            .ws_url
+            .as_ref()
+            .ok_or_else(|| "The BiDi endpoint is not known yet, call wait_for_endpoint first".to_string())?;
+        BidiSession::connect(ws_url)
+    }
 }
 
 pub struct Launcher {
@@ -55,42 +226,212 @@ pub struct Launcher {
     err_file: PathBuf,
     browser_path: Option<String>,
     env_vars: HashMap<String, String>,
+    prefs: HashMap<String, serde_json::Value>,
+    /// Origin of every option field that was set, for launch plans
+    field_sources: HashMap<&'static str, FlagSource>,
+    /// Env vars and config file keys that were left out because they are invalid
+    config_errors: Vec<String>,
+    /// Env vars that were ignored without failing the launch
+    config_warnings: Vec<String>,
     port: u16,
     ignore_default_flags: bool,
-    #[allow(dead_code)]
     connection_poll_interval: u64,
-    #[allow(dead_code)]
     max_connection_retries: u32,
     user_data_dir: String,
     browser_flags: Vec<String>,
     starting_url: String,
+    starting_urls: Vec<String>,
     browser_type: BrowserType,
+    protocol: Protocol,
     headless: bool,
     window_size: Option<(u32, u32)>,
+    device: Option<DevicePreset>,
+    window_position: Option<(i32, i32)>,
+    start_maximized: bool,
+    fullscreen: bool,
+    kiosk: bool,
+    app_url: Option<String>,
+    virtual_display: bool,
+    virtual_display_size: Option<(u32, u32)>,
+    ozone_platform: Option<OzonePlatform>,
+    resource_limits: Option<ResourceLimits>,
+    process_controls: ProcessControls,
     incognito: bool,
     disable_gpu: bool,
     no_sandbox: bool,
+    sandbox_policy: SandboxPolicy,
+    host: HostEnvironment,
+    sandbox_wrapper: Option<SandboxWrapper>,
+    wrapper: Vec<String>,
     disable_web_security: bool,
     allow_running_insecure_content: bool,
     ignore_ssl_errors: bool,
+    /// SPKI hashes of `trusted_certificates`, or why a certificate failed to load
+    trusted_spki_hashes: Result<Vec<String>, String>,
     disable_extensions: bool,
+    /// Loaded `extensions`, or why one of them failed to load
+    extensions: Result<Vec<Extension>, String>,
+    native_messaging_hosts: Vec<NativeMessagingHost>,
     disable_plugins: bool,
     disable_images: bool,
     disable_javascript: bool,
+    handle_sigint: bool,
+    log_level: Option<String>,
     user_agent: Option<String>,
-    proxy_server: Option<String>,
-    host_resolver_rules: Option<String>,
+    locale: Option<String>,
+    timezone: Option<String>,
+    /// Resolved from `proxy` or `proxy_server`, with the field it came from;
+    /// an invalid configuration is reported by `try_new`, `plan` and `launch`
+    proxy: ResolvedProxy,
+    /// Authenticated upstream that `launch` starts a local forwarder for, if needed
+    proxy_forwarder: Option<ProxyServer>,
+    /// Port of the forwarder while `launch` builds its plan; 0 in plans made before
+    forwarder_port: u16,
+    host_resolver_rules: Option<HostResolverRules>,
     additional_args: Vec<String>,
 }
 
+/// SIGKILL the browser under a wrapper; one that already exited is not an error
+#[cfg(unix)]
+fn kill_browser(pid: u32) -> std::io::Result<()> {
+    match crate::utils::send_signal(pid, libc::SIGKILL) {
+        Err(e) if e.raw_os_error() != Some(libc::ESRCH) => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_browser(_pid: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Move the child into `cgroup` between fork and exec, so every process the
+/// browser starts is created inside it
+#[cfg(unix)]
+fn join_cgroup_before_exec(command: &mut Command, cgroup: &Cgroup) -> Result<(), String> {
+    use std::io::Write;
+    use std::os::unix::process::CommandExt;
+
+    let procs = cgroup.open_procs()?;
+    // Safety: the hook only issues a write(2) on an already open file
+    unsafe {
+        command.pre_exec(move || (&procs).write_all(b"0"));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn join_cgroup_before_exec(_command: &mut Command, _cgroup: &Cgroup) -> Result<(), String> {
+    Err("resource_limits require Linux with cgroup v2".to_string())
+}
+
+/// Chromium `--log-level` (`None` for verbose, which uses `--v=1`) and Firefox
+/// `remote.log.level` for a `log_level` option
+fn log_level_settings(level: &str) -> Result<(Option<u8>, &'static str), String> {
+    match level.to_ascii_lowercase().as_str() {
+        "verbose" => Ok((None, "Trace")),
+        "info" => Ok((Some(0), "Info")),
+        "warning" | "warn" => Ok((Some(1), "Warn")),
+        "error" => Ok((Some(2), "Error")),
+        "silent" => Ok((Some(3), "Fatal")),
+        _ => Err(format!("Invalid log_level '{}' (expected verbose, info, warning, error or silent)", level)),
+    }
+}
+
+/// A validated proxy configuration and the option field it came from
+type ResolvedProxy = Result<Option<(ProxyConfig, &'static str)>, String>;
+
+/// The proxy configuration from `proxy` or the `proxy_server` shorthand.
+///
+/// When both are set, the one from the higher layer (option, env, config
+/// file) wins; both in the same layer is an error.
+fn resolve_proxy(
+    proxy: Option<ProxyConfig>,
+    proxy_server: Option<String>,
+    sources: &HashMap<&'static str, FlagSource>,
+) -> ResolvedProxy {
+    let layer = |field: &str| match sources.get(field) {
+        Some(FlagSource::ConfigFile(_)) => 0,
+        Some(FlagSource::Env(_)) => 1,
+        _ => 2,
+    };
+    let (proxy, proxy_server) = match (proxy, proxy_server) {
+        (Some(_), Some(_)) if layer("proxy") == layer("proxy_server") => {
+            return Err("Set either proxy or proxy_server, not both".to_string())
+        }
+        (Some(_), Some(server)) if layer("proxy") < layer("proxy_server") => (None, Some(server)),
+        (Some(proxy), _) => (Some(proxy), None),
+        (None, server) => (None, server),
+    };
+    match (proxy, proxy_server) {
+        (Some(proxy), _) => proxy.validate().map(|_| Some((proxy, "proxy"))),
+        (None, Some(server)) => {
+            let proxy = server.parse::<ProxyConfig>()?;
+            match proxy {
+                ProxyConfig::Manual { .. } => Ok(Some((proxy, "proxy_server"))),
+                _ => Err(format!("Invalid proxy_server '{}', use the proxy option for direct, system or PAC settings", server)),
+            }
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+
+/// A fresh profile directory under the system temp dir
+fn default_user_data_dir() -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    env::temp_dir().join(format!("browser_launcher-{}-{}", std::process::id(), nanos))
+}
 
 impl Launcher {
+    /// Like `new`, but fail on options that can never work, such as a
+    /// malformed proxy or an out-of-range `nice`
+    pub fn try_new(opts: Options) -> Result<Self, String> {
+        let launcher = Self::new(opts);
+        launcher.check_options()?;
+        Ok(launcher)
+    }
+
+    /// Report options that failed to parse or validate
+    fn check_options(&self) -> Result<(), String> {
+        if !self.config_errors.is_empty() {
+            return Err(self.config_errors.join("; "));
+        }
+        self.process_controls.validate()?;
+        self.proxy.as_ref().map_err(Clone::clone)?;
+        self.trusted_spki_hashes.as_ref().map_err(Clone::clone)?;
+        self.check_locale_options()?;
+        if let Some(ref level) = self.log_level {
+            log_level_settings(level)?;
+        }
+        self.check_window_options()?;
+        self.check_extensions()?;
+        self.native_messaging_manifests()?;
+        Ok(())
+    }
+
+    /// Build a launcher; invalid options are reported by `plan` and `launch`
     pub fn new(opts: Options) -> Self {
+        // Explicit options win over BROWSER_LAUNCHER_* env vars, which win over the config file
+        let layers = config::apply_layers(opts);
+        let (opts, field_sources) = (layers.options, layers.sources);
+        let config_errors = layers.errors.into_iter().map(|e| e.message).collect();
+        let config_warnings = layers.warnings.into_iter().map(|e| e.message).collect();
         let user_data_dir = opts
             .user_data_dir
             .as_ref()
             .map(PathBuf::from)
-            .unwrap_or_else(env::temp_dir);
+            .unwrap_or_else(default_user_data_dir);
+        let proxy = resolve_proxy(opts.proxy, opts.proxy_server, &field_sources);
+        // Browsers ignore credentials in proxy URLs, so an authenticated
+        // upstream is reached through a local forwarder
+        let proxy_forwarder = match proxy {
+            Ok(Some((ref config, _))) => config.authenticated_upstream().cloned(),
+            _ => None,
+        };
         let out_file = user_data_dir.join("browser-out.log");
         let err_file = user_data_dir.join("browser-err.log");
         Self {
@@ -98,55 +439,627 @@ impl Launcher {
             out_file,
             err_file,
             browser_path: opts.browser_path,
-            env_vars: get_default(opts.env_vars, || env::vars().collect()),
+            // The child inherits our environment; env_vars only adds to it
+            env_vars: get_default(opts.env_vars, HashMap::new),
+            prefs: get_default(opts.prefs, HashMap::new),
+            field_sources,
+            config_errors,
+            config_warnings,
             port: get_default(opts.port, || 0),
             ignore_default_flags: get_default(opts.ignore_default_flags, || false),
             connection_poll_interval: get_default(opts.connection_poll_interval, || 500),
             max_connection_retries: get_default(opts.max_connection_retries, || 50),
-            user_data_dir: get_default(opts.user_data_dir, || user_data_dir.to_string_lossy().to_owned().to_string()),
+            user_data_dir: get_default(opts.user_data_dir, || user_data_dir.to_string_lossy().to_string()),
             browser_flags: get_default(opts.browser_flags, || [].to_vec()),
             starting_url: get_default(opts.starting_url, || "about:blank".to_owned()),
+            starting_urls: get_default(opts.starting_urls, Vec::new),
+            protocol: opts
+                .protocol
+                .unwrap_or_else(|| opts.browser.as_ref().unwrap_or(&BrowserType::Chrome).default_protocol()),
             browser_type: get_default(opts.browser, || BrowserType::Chrome),
             headless: get_default(opts.headless, || false),
             window_size: opts.window_size,
+            device: opts.device,
+            window_position: opts.window_position,
+            start_maximized: get_default(opts.start_maximized, || false),
+            fullscreen: get_default(opts.fullscreen, || false),
+            kiosk: get_default(opts.kiosk, || false),
+            app_url: opts.app_url,
+            virtual_display: get_default(opts.virtual_display, || false),
+            virtual_display_size: opts.virtual_display_size,
+            ozone_platform: opts.ozone_platform,
+            resource_limits: opts.resource_limits,
+            process_controls: ProcessControls {
+                nice: opts.nice,
+                cpu_affinity: opts.cpu_affinity,
+                oom_score_adj: opts.oom_score_adj,
+            },
             incognito: get_default(opts.incognito, || false),
             disable_gpu: get_default(opts.disable_gpu, || false),
             no_sandbox: get_default(opts.no_sandbox, || false),
+            sandbox_policy: get_default(opts.sandbox_policy, || SandboxPolicy::Auto),
+            host: HostEnvironment::detect(),
+            sandbox_wrapper: opts.sandbox_wrapper,
+            wrapper: get_default(opts.wrapper, Vec::new),
             disable_web_security: get_default(opts.disable_web_security, || false),
             allow_running_insecure_content: get_default(opts.allow_running_insecure_content, || false),
             ignore_ssl_errors: get_default(opts.ignore_ssl_errors, || false),
+            trusted_spki_hashes: opts
+                .trusted_certificates
+                .unwrap_or_default()
+                .iter()
+                .map(|path| certs::spki_hashes(path))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|hashes| hashes.concat()),
             disable_extensions: get_default(opts.disable_extensions, || false),
+            extensions: opts
+                .extensions
+                .unwrap_or_default()
+                .iter()
+                .map(|dir| Extension::load(dir))
+                .collect(),
+            native_messaging_hosts: get_default(opts.native_messaging_hosts, Vec::new),
             disable_plugins: get_default(opts.disable_plugins, || false),
             disable_images: get_default(opts.disable_images, || false),
             disable_javascript: get_default(opts.disable_javascript, || false),
+            handle_sigint: get_default(opts.handle_sigint, || false),
+            log_level: opts.log_level,
             user_agent: opts.user_agent,
-            proxy_server: opts.proxy_server,
+            locale: opts.locale,
+            timezone: opts.timezone,
+            proxy,
+            proxy_forwarder,
+            forwarder_port: 0,
             host_resolver_rules: opts.host_resolver_rules,
-            additional_args: get_default(opts.additional_args, || vec![]),
+            additional_args: get_default(opts.additional_args, Vec::new),
         }
     }
 
     pub fn launch(&mut self) -> Result<LaunchedBrowser, String> {
-        let browser_path = if let Some(ref path) = self.browser_path {
-            path.as_str()
-        } else {
-            &self.get_browser_path()?
+        // Bind the forwarder first so the plan points the browser at the port it got
+        let proxy_forwarder = match self.proxy_forwarder {
+            Some(ref upstream) => Some(ProxyForwarder::start(0, upstream.clone())?),
+            None => None,
         };
-        let mut command = Command::new(browser_path);
+        self.forwarder_port = proxy_forwarder.as_ref().map_or(0, |forwarder| forwarder.port);
+        let plan = self.plan();
+        self.forwarder_port = 0;
+        let plan = plan?;
+        let virtual_display = match plan.virtual_display {
+            Some(size) => Some(VirtualDisplay::start(size)?),
+            None => None,
+        };
+        let cgroup = match plan.resource_limits {
+            Some(ref limits) => Some(Cgroup::create(limits)?),
+            None => None,
+        };
+        for action in &plan.profile_actions {
+            action.apply()?;
+        }
+        let native_messaging_hosts = plan
+            .native_messaging_hosts
+            .iter()
+            .map(|(path, manifest)| InstalledNativeHost::install(path, manifest))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        command.args(self.get_flags());
+        let mut command = match plan.wrapper.split_first() {
+            Some((wrapper, wrapper_args)) => {
+                let mut command = Command::new(&wrapper.value);
+                command.args(wrapper_args.iter().map(|arg| &arg.value));
+                command.arg(&plan.executable);
+                command
+            }
+            None => Command::new(&plan.executable),
+        };
+        command.args(plan.arg_values());
         command.stdout(File::create(&self.out_file).map_err(|e| e.to_string())?);
         command.stderr(File::create(&self.err_file).map_err(|e| e.to_string())?);
-        command.envs(&self.env_vars);
+        command.envs(plan.env.iter().map(|var| (&var.name, &var.value)));
+        if let Some(ref display) = virtual_display {
+            command.env("DISPLAY", display.name());
+        }
 
-        let child = command.spawn().map_err(|e| e.to_string())?;
-        let pid = child.id();
+        if let Some(ref cgroup) = cgroup {
+            join_cgroup_before_exec(&mut command, cgroup)?;
+        }
+        plan.process_controls.install(&mut command);
+
+        let child = command.spawn().map_err(|e| {
+            if plan.process_controls.is_empty() {
+                e.to_string()
+            } else {
+                format!("{} (while applying {})", e, plan.process_controls)
+            }
+        })?;
         self.browser_process = Some(child);
 
-        let process = self.browser_process.take().unwrap();
+        let mut process = self.browser_process.take().unwrap();
+        let mut warnings = plan.warnings;
+        let pid = match plan.wrapper.is_empty() {
+            true => process.id(),
+            false => match wrapper::wait_for_browser_pid(&mut process, &plan.executable)? {
+                Some(pid) => pid,
+                None => {
+                    warnings.push(format!(
+                        "{} was not found under the wrapper process {}; tracking the wrapper instead",
+                        plan.executable,
+                        process.id()
+                    ));
+                    process.id()
+                }
+            },
+        };
+        // Wrappers such as taskset exec the browser in place
+        let wrapper_pid = Some(process.id()).filter(|&wrapper_pid| wrapper_pid != pid);
         let port = self.port;
 
-        Ok(LaunchedBrowser { pid, port, process })
+        Ok(LaunchedBrowser {
+            pid,
+            wrapper_pid,
+            port,
+            process,
+            user_data_dir: self.user_data_dir.clone(),
+            ws_url: None,
+            protocol: self.protocol,
+            virtual_display,
+            cgroup,
+            proxy_forwarder,
+            extensions: plan.extensions,
+            native_messaging_hosts,
+            warnings,
+            pending_urls: plan.deferred_urls,
+        })
+    }
+
+    /// Wait until the browser reports its DevTools endpoint, then record the
+    /// real port and websocket URL on `browser`.
+    ///
+    /// The endpoint is read from `DevToolsActivePort`; with an explicit `port`
+    /// the websocket URL may stay unknown until `wait_until_ready`.
+    pub fn wait_for_endpoint(&self, browser: &mut LaunchedBrowser) -> Result<(), String> {
+        if self.browser_type.is_firefox() {
+            // Firefox announces the Remote Agent on stderr instead of writing DevToolsActivePort
+            let url = self.poll(browser, "the WebDriver BiDi endpoint", |_| {
+                devtools::read_bidi_listening_url(&self.err_file)
+            })?;
+            browser.port = devtools::port_from_ws_url(&url).unwrap_or(self.port);
+            browser.ws_url = Some(format!("{}/session", url.trim_end_matches('/')));
+            return Ok(());
+        }
+
+        let user_data_dir = PathBuf::from(&browser.user_data_dir);
+        let (port, path) = self.poll(browser, "the DevTools endpoint", |_| {
+            devtools::read_active_port(&user_data_dir).or_else(|| {
+                // Without DevToolsActivePort only an explicitly chosen port is known
+                (self.port != 0).then(|| (self.port, String::new()))
+            })
+        })?;
+        browser.port = port;
+        if !path.is_empty() {
+            browser.ws_url = Some(format!("ws://127.0.0.1:{}{}", port, path));
+        }
+        Ok(())
+    }
+
+    /// Wait until the browser answers on its `protocol` endpoint: `/json/version`
+    /// for CDP, `session.status` for WebDriver BiDi.
+    ///
+    /// Calls `wait_for_endpoint` first if the port is not known yet.
+    pub fn wait_until_ready(&self, browser: &mut LaunchedBrowser) -> Result<(), String> {
+        if browser.port == 0 || browser.ws_url.is_none() {
+            self.wait_for_endpoint(browser)?;
+        }
+        let port = browser.port;
+        if self.protocol == Protocol::Bidi {
+            let ws_url = browser.ws_url.clone().unwrap_or_default();
+            return self.poll(browser, "WebDriver BiDi to answer", |_| {
+                let mut session = BidiSession::connect(&ws_url).ok()?;
+                session.status().ok().map(|_| ())
+            });
+        }
+        let version = self.poll(browser, "DevTools to answer", |_| devtools::version(port).ok())?;
+        if browser.ws_url.is_none() {
+            browser.ws_url = version["webSocketDebuggerUrl"].as_str().map(String::from);
+        }
+        for url in std::mem::take(&mut browser.pending_urls) {
+            devtools::open_tab(port, &url).map_err(|e| format!("Failed to open {}: {}", url, e))?;
+        }
+        Ok(())
+    }
+
+    /// Run `check` every `connection_poll_interval` ms, up to
+    /// `max_connection_retries` times, failing early if the browser exits
+    fn poll<T, F>(&self, browser: &mut LaunchedBrowser, what: &str, mut check: F) -> Result<T, String>
+    where
+        F: FnMut(&LaunchedBrowser) -> Option<T>,
+    {
+        for _ in 0..self.max_connection_retries.max(1) {
+            if let Ok(Some(status)) = browser.process.try_wait() {
+                return Err(format!(
+                    "Browser exited ({}) while waiting for {}, see {}",
+                    status,
+                    what,
+                    self.err_file.display()
+                ));
+            }
+            if let Some(value) = check(browser) {
+                return Ok(value);
+            }
+            thread::sleep(Duration::from_millis(self.connection_poll_interval));
+        }
+        Err(format!(
+            "Timed out waiting for {} after {} attempts",
+            what, self.max_connection_retries
+        ))
+    }
+
+    /// Resolve everything `launch` would do without spawning the browser.
+    ///
+    /// Each argument and environment variable is annotated with its source.
+    pub fn plan(&self) -> Result<LaunchPlan, String> {
+        self.check_protocol()?;
+        self.check_sandbox()?;
+        self.check_options()?;
+        let user_data_dir = PathBuf::from(&self.user_data_dir);
+        let mut profile_actions = Vec::new();
+        if !user_data_dir.exists() {
+            profile_actions.push(ProfileAction::CreateDir(user_data_dir.clone()));
+        }
+        if let Some(dir) = self.downloads_dir().filter(|dir| !dir.exists()) {
+            profile_actions.push(ProfileAction::CreateDir(dir));
+        }
+        if self.browser_type.is_firefox() {
+            profile_actions.push(ProfileAction::WriteUserJs {
+                path: user_data_dir.join("user.js"),
+                prefs: self.get_firefox_prefs(),
+            });
+        } else {
+            // A leftover DevToolsActivePort would make readiness report a stale port
+            profile_actions.push(ProfileAction::RemoveFile(user_data_dir.join(devtools::ACTIVE_PORT_FILE)));
+            let prefs = self.get_chromium_prefs();
+            if !prefs.is_empty() {
+                profile_actions.push(ProfileAction::WritePreferences {
+                    path: user_data_dir.join("Default").join("Preferences"),
+                    prefs,
+                });
+            }
+        }
+
+        let mut env: Vec<PlannedEnvVar> = self
+            .env_vars
+            .iter()
+            .map(|(name, value)| PlannedEnvVar {
+                name: name.clone(),
+                value: value.clone(),
+                source: self.source_of("env_vars"),
+            })
+            .collect();
+        if let Some(ref timezone) = self.timezone {
+            env.push(PlannedEnvVar {
+                name: "TZ".to_string(),
+                value: timezone.clone(),
+                source: self.source_of("timezone"),
+            });
+        }
+        env.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let executable = self.browser_executable()?;
+        let mut wrapper: Vec<PlannedArg> = self
+            .wrapper
+            .iter()
+            .map(|arg| PlannedArg::new(arg.clone(), self.source_of("wrapper")))
+            .collect();
+        if let Some(ref sandbox) = self.sandbox_wrapper {
+            let source = self.source_of("sandbox_wrapper");
+            wrapper.extend(
+                sandbox
+                    .command_prefix(&user_data_dir, &executable)?
+                    .into_iter()
+                    .map(|arg| PlannedArg::new(arg, source.clone())),
+            );
+        }
+
+        Ok(LaunchPlan {
+            wrapper,
+            executable,
+            args: self.get_planned_args(),
+            env,
+            profile_actions,
+            virtual_display: self.virtual_display_size(),
+            resource_limits: self.resource_limits.clone(),
+            process_controls: self.process_controls.clone(),
+            proxy_forwarder: self.proxy_forwarder.clone().map(|upstream| (self.forwarder_port, upstream)),
+            extensions: self.loaded_extensions().to_vec(),
+            native_messaging_hosts: self.native_messaging_manifests()?,
+            deferred_urls: self.split_starting_urls().1,
+            warnings: self.warnings(),
+        })
+    }
+
+    /// Problems with valid options that a launch can still go ahead with
+    fn warnings(&self) -> Vec<String> {
+        let mut warnings = self.config_warnings.clone();
+        if let Some(Ok(Some(warning))) = self.timezone.as_deref().map(locale::validate_timezone) {
+            warnings.push(warning);
+        }
+        warnings
+    }
+
+    /// Validate `locale` and `timezone`
+    fn check_locale_options(&self) -> Result<(), String> {
+        if let Some(ref locale) = self.locale {
+            locale::validate_locale(locale)?;
+        }
+        if let Some(ref timezone) = self.timezone {
+            if self.env_vars.contains_key("TZ") {
+                return Err("timezone cannot be combined with TZ in env_vars".to_string());
+            }
+            locale::validate_timezone(timezone)?;
+        }
+        Ok(())
+    }
+
+    /// Reject window modes that contradict each other or need a visible window
+    fn check_window_options(&self) -> Result<(), String> {
+        let modes = [
+            ("window_position", self.window_position.is_some()),
+            ("start_maximized", self.start_maximized),
+            ("fullscreen", self.fullscreen),
+            ("kiosk", self.kiosk),
+            ("app_url", self.app_url.is_some()),
+        ];
+        if let (true, Some((field, _))) = (self.headless, modes.iter().find(|(_, set)| *set)) {
+            return Err(format!("{} cannot be combined with headless", field));
+        }
+        let conflicts = [
+            ("start_maximized", self.start_maximized, "fullscreen", self.fullscreen),
+            ("kiosk", self.kiosk, "start_maximized", self.start_maximized),
+            ("kiosk", self.kiosk, "fullscreen", self.fullscreen),
+            ("starting_url", self.field_sources.contains_key("starting_url"), "starting_urls", !self.starting_urls.is_empty()),
+        ];
+        if let Some((a, _, b, _)) = conflicts.iter().find(|(_, a, _, b)| *a && *b) {
+            return Err(format!("{} cannot be combined with {}", a, b));
+        }
+        if let Some(ref url) = self.app_url {
+            if !url.contains(':') || url.chars().any(char::is_whitespace) {
+                return Err(format!("app_url must be an absolute URL, got '{}'", url));
+            }
+        }
+        Ok(())
+    }
+
+    /// The URLs to open: `starting_urls`, or else `starting_url`
+    fn urls(&self) -> Vec<(String, FlagSource)> {
+        if self.starting_urls.is_empty() {
+            return vec![(self.starting_url.clone(), self.source_of("starting_url"))];
+        }
+        let source = self.source_of("starting_urls");
+        self.starting_urls.iter().map(|url| (url.clone(), source.clone())).collect()
+    }
+
+    /// Split the URLs into positional arguments and URLs opened over DevTools
+    /// after startup, since Chromium drops extra positional URLs in app and kiosk mode
+    fn split_starting_urls(&self) -> (Vec<PlannedArg>, Vec<String>) {
+        let mut urls = self.urls();
+        if self.browser_type.is_firefox() {
+            return (urls.into_iter().map(|(url, source)| PlannedArg::new(url, source)).collect(), Vec::new());
+        }
+        let positional = match (self.app_url.is_some(), self.kiosk) {
+            // The app window takes the place of the first URL; the default about:blank is not opened
+            (true, _) => {
+                if self.starting_urls.is_empty() && !self.field_sources.contains_key("starting_url") {
+                    urls.clear();
+                }
+                0
+            }
+            (false, true) => 1,
+            (false, false) => urls.len(),
+        };
+        let deferred = urls.split_off(positional).into_iter().map(|(url, _)| url).collect();
+        (urls.into_iter().map(|(url, source)| PlannedArg::new(url, source)).collect(), deferred)
+    }
+
+    /// Extensions to load; empty when any of them failed to load
+    fn loaded_extensions(&self) -> &[Extension] {
+        self.extensions.as_deref().unwrap_or_default()
+    }
+
+    /// Reject extensions that failed to load or cannot be combined with the other options
+    fn check_extensions(&self) -> Result<(), String> {
+        let extensions = self.extensions.as_ref().map_err(Clone::clone)?;
+        if extensions.is_empty() {
+            return Ok(());
+        }
+        if self.browser_type.is_firefox() {
+            return Err(format!(
+                "{} cannot load unpacked extensions from the command line",
+                self.browser_type.name()
+            ));
+        }
+        if self.disable_extensions {
+            return Err("extensions cannot be combined with disable_extensions".to_string());
+        }
+        Ok(())
+    }
+
+    /// Manifest path and content of each native messaging host; hosts without
+    /// `allowed_origins` are opened to every loaded extension
+    fn native_messaging_manifests(&self) -> Result<Vec<(PathBuf, serde_json::Value)>, String> {
+        if !self.native_messaging_hosts.is_empty() && self.browser_type.is_firefox() {
+            return Err(format!(
+                "Native messaging hosts are only supported for Chromium-based browsers, not {}",
+                self.browser_type.name()
+            ));
+        }
+        let extension_origins: Vec<String> = self
+            .loaded_extensions()
+            .iter()
+            .map(|extension| format!("chrome-extension://{}/", extension.id))
+            .collect();
+        self.native_messaging_hosts
+            .iter()
+            .map(|host| {
+                host.validate()?;
+                let origins = match host.allowed_origins.is_empty() {
+                    true => &extension_origins,
+                    false => &host.allowed_origins,
+                };
+                if origins.is_empty() {
+                    return Err(format!(
+                        "Native messaging host {} has no allowed origins; set allowed_origins or load extensions",
+                        host.name
+                    ));
+                }
+                let path = host.manifest_path(&self.browser_type, Path::new(&self.user_data_dir))?;
+                Ok((path, host.manifest(origins)))
+            })
+            .collect()
+    }
+
+    /// The writable downloads directory of the sandbox wrapper, if it has one
+    fn downloads_dir(&self) -> Option<PathBuf> {
+        self.sandbox_wrapper
+            .as_ref()
+            .and_then(|sandbox| sandbox.downloads_dir(Path::new(&self.user_data_dir)))
+    }
+
+    /// The Ozone platform to select: the explicit option, X11 for a virtual
+    /// display, or whatever the session variables point at
+    fn ozone_platform(&self) -> Option<(OzonePlatform, FlagSource)> {
+        if let Some(platform) = self.ozone_platform {
+            return Some((platform, self.source_of("ozone_platform")));
+        }
+        if self.ignore_default_flags || self.headless || !cfg!(target_os = "linux") {
+            return None;
+        }
+        if self.virtual_display_size().is_some() {
+            return Some((OzonePlatform::X11, self.source_of("virtual_display")));
+        }
+        // The browser sees our environment plus env_vars
+        detect_ozone_platform(|name| self.env_vars.get(name).cloned().or_else(|| env::var(name).ok()))
+            .map(|(platform, var)| (platform, FlagSource::Env(var.to_string())))
+    }
+
+    /// `window_size`, or else the size of the `device` preset, with its source
+    fn window_size(&self) -> Option<((u32, u32), FlagSource)> {
+        match (self.window_size, self.device) {
+            (Some(size), _) => Some((size, self.source_of("window_size"))),
+            (None, Some(device)) => Some((device.window_size, FlagSource::Preset(device.id.to_string()))),
+            (None, None) => None,
+        }
+    }
+
+    /// `user_agent`, or else the user agent of the `device` preset, with its source
+    fn user_agent(&self) -> Option<(&str, FlagSource)> {
+        match (&self.user_agent, self.device) {
+            (Some(ua), _) => Some((ua.as_str(), self.source_of("user_agent"))),
+            (None, Some(device)) => Some((device.user_agent, FlagSource::Preset(device.id.to_string()))),
+            (None, None) => None,
+        }
+    }
+
+    /// Xvfb screen size, when a virtual display is wanted
+    fn virtual_display_size(&self) -> Option<(u32, u32)> {
+        (self.virtual_display && !self.headless).then(|| {
+            self.virtual_display_size
+                .or(self.window_size().map(|(size, _)| size))
+                .unwrap_or(DEFAULT_SCREEN_SIZE)
+        })
+    }
+
+    /// Whether the host checks of `sandbox_policy` apply to this launch
+    fn inspects_host(&self) -> bool {
+        self.sandbox_policy != SandboxPolicy::Off && !self.ignore_default_flags && !self.browser_type.is_firefox()
+    }
+
+    /// Switches the host needs under `sandbox_policy`
+    fn get_host_flags(&self) -> Vec<PlannedArg> {
+        let mut flags = Vec::new();
+        if !self.inspects_host() {
+            return flags;
+        }
+        if let (false, SandboxPolicy::Auto, Some(reason)) = (self.no_sandbox, self.sandbox_policy, self.host.sandbox_blocker()) {
+            flags.push(PlannedArg::new("--no-sandbox", FlagSource::Host(reason)));
+        }
+        if self.host.small_dev_shm() {
+            let size = self.host.dev_shm_size.unwrap_or_default() / (1024 * 1024);
+            flags.push(PlannedArg::new(
+                "--disable-dev-shm-usage",
+                FlagSource::Host(format!("/dev/shm is only {} MiB", size)),
+            ));
+        }
+        flags
+    }
+
+    /// Under the strict policy, refuse hosts where the sandbox cannot start
+    fn check_sandbox(&self) -> Result<(), String> {
+        if !self.inspects_host() || self.sandbox_policy != SandboxPolicy::Strict || self.no_sandbox {
+            return Ok(());
+        }
+        match self.host.sandbox_blocker() {
+            Some(reason) => Err(format!(
+                "The Chromium sandbox cannot start because {}{}. Run the browser as an unprivileged user with \
+                 user namespaces enabled, or set sandbox_policy to auto to launch with --no-sandbox",
+                reason,
+                if self.host.in_container { " inside a container" } else { "" }
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Reject protocols the browser cannot serve by itself
+    fn check_protocol(&self) -> Result<(), String> {
+        match (self.protocol, self.browser_type.is_firefox()) {
+            (Protocol::Cdp, true) => Err(format!(
+                "{} does not serve the Chrome DevTools Protocol, use the bidi protocol",
+                self.browser_type.name()
+            )),
+            (Protocol::Bidi, false) => Err(format!(
+                "{} serves WebDriver BiDi only through chromedriver, use the cdp protocol",
+                self.browser_type.name()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Resolve the browser executable that `launch` would run
+    pub fn browser_executable(&self) -> Result<String, String> {
+        match self.browser_path {
+            Some(ref path) => Ok(path.clone()),
+            None => self.get_browser_path(),
+        }
+    }
+
+    /// The arguments that `launch` passes to the browser
+    pub fn command_args(&self) -> Vec<String> {
+        self.get_flags()
+    }
+
+    /// The proxy configuration given to the browser, with an authenticated
+    /// upstream replaced by the local forwarder
+    fn browser_proxy(&self) -> Option<(ProxyConfig, &'static str)> {
+        let Ok(Some((ref proxy, field))) = self.proxy else {
+            return None;
+        };
+        match self.proxy_forwarder {
+            Some(_) => Some((proxy.via_forwarder(self.forwarder_port), field)),
+            None => Some((proxy.clone(), field)),
+        }
+    }
+
+    /// Where the value of an option field came from
+    fn source_of(&self, field: &'static str) -> FlagSource {
+        self.field_sources.get(field).cloned().unwrap_or(FlagSource::Default)
+    }
+
+    /// The browser type this launcher targets
+    pub fn browser_type(&self) -> &BrowserType {
+        &self.browser_type
+    }
+
+    /// Whether the browser should be shut down on SIGINT
+    pub fn handle_sigint(&self) -> bool {
+        self.handle_sigint
     }
 
     pub fn kill(&mut self) {
@@ -159,7 +1072,7 @@ impl Launcher {
     // Public getter methods for testing
     #[cfg(test)]
     pub fn get_starting_url(&self) -> &str {
-        &self.starting_url
+        self.starting_urls.first().unwrap_or(&self.starting_url)
     }
 
     #[cfg(test)]
@@ -183,6 +1096,7 @@ impl Launcher {
     }
 
     #[cfg(test)]
+    #[allow(clippy::type_complexity)]
     pub fn get_all_config(&self) -> (&str, u16, bool, bool, bool, bool, bool, bool, bool, bool, bool, bool, &BrowserType, Option<&(u32, u32)>, &Vec<String>, &Vec<String>, &str) {
         (
             &self.starting_url,
@@ -240,10 +1154,17 @@ impl Launcher {
     }
 
     fn get_flags(&self) -> Vec<String> {
-        let mut flags = match self.browser_type {
+        self.get_planned_args().into_iter().map(|arg| arg.value).collect()
+    }
+
+    fn get_planned_args(&self) -> Vec<PlannedArg> {
+        let mut args = match self.browser_type {
             BrowserType::Chrome | BrowserType::ChromeCanary | BrowserType::Chromium | BrowserType::Edge | BrowserType::Brave | BrowserType::Opera | BrowserType::Vivaldi => {
                 self.get_chromium_flags()
             }
+            BrowserType::Firefox | BrowserType::FirefoxEsr | BrowserType::FirefoxNightly | BrowserType::FirefoxDeveloperEdition => {
+                self.get_firefox_flags()
+            }
             BrowserType::Custom(_) => {
                 // Default to chromium flags for custom browsers if they are chromium-based
                 // In the future, we could detect or allow more specific custom flags
@@ -251,115 +1172,288 @@ impl Launcher {
             }
         };
 
-        // Starting URL (must be last for most browsers)
-        flags.push(self.starting_url.clone());
+        // Starting URLs (must be last for most browsers)
+        args.extend(self.split_starting_urls().0);
 
-        flags
+        args
     }
 
-    fn get_chromium_flags(&self) -> Vec<String> {
+    fn get_chromium_flags(&self) -> Vec<PlannedArg> {
         let mut flags = if self.ignore_default_flags {
             vec![]
         } else {
+            // --disable-extensions would also block the extensions to load
             DEFAULT_FLAGS
                 .iter()
-                .map(|&s| s.to_string())
-                .collect::<Vec<String>>()
+                .filter(|&&s| s != "--disable-extensions" || self.loaded_extensions().is_empty())
+                .map(|&s| PlannedArg::new(s, FlagSource::Default))
+                .collect::<Vec<PlannedArg>>()
         };
 
         // Add remote debugging port
-        flags.push(format!("--remote-debugging-port={}", self.port));
+        flags.push(PlannedArg::new(format!("--remote-debugging-port={}", self.port), self.source_of("port")));
 
         // Platform-specific flags
         if !self.ignore_default_flags && cfg!(target_os = "linux") {
-            flags.push("--disable-setuid-sandbox".to_string());
+            flags.push(PlannedArg::new("--disable-setuid-sandbox", FlagSource::Default));
         }
 
         // User data directory
-        flags.push(format!("--user-data-dir={}", self.user_data_dir));
+        flags.push(PlannedArg::new(format!("--user-data-dir={}", self.user_data_dir), self.source_of("user_data_dir")));
 
         // Headless mode
-        if self.headless || env::var("HEADLESS").is_ok() {
-            flags.push("--headless".to_string());
-            flags.push("--disable-gpu".to_string()); // GPU is disabled in headless by default
+        if self.headless {
+            let source = self.source_of("headless");
+            flags.push(PlannedArg::new("--headless", source.clone()));
+            flags.push(PlannedArg::new("--disable-gpu", source)); // GPU is disabled in headless by default
         }
 
         // Window size
-        if let Some((width, height)) = self.window_size {
-            flags.push(format!("--window-size={},{}", width, height));
+        if let Some(((width, height), source)) = self.window_size() {
+            flags.push(PlannedArg::new(format!("--window-size={},{}", width, height), source));
+        }
+
+        // Device scale factor and touch events
+        if let Some(device) = self.device {
+            let source = FlagSource::Preset(device.id.to_string());
+            flags.extend(device.chromium_flags().into_iter().map(|flag| PlannedArg::new(flag, source.clone())));
+        }
+
+        // Window placement and modes
+        if let Some((x, y)) = self.window_position {
+            flags.push(PlannedArg::new(format!("--window-position={},{}", x, y), self.source_of("window_position")));
+        }
+        if self.start_maximized {
+            flags.push(PlannedArg::new("--start-maximized", self.source_of("start_maximized")));
+        }
+        if self.fullscreen {
+            flags.push(PlannedArg::new("--start-fullscreen", self.source_of("fullscreen")));
+        }
+        if self.kiosk {
+            flags.push(PlannedArg::new("--kiosk", self.source_of("kiosk")));
+        }
+        if let Some(ref url) = self.app_url {
+            flags.push(PlannedArg::new(format!("--app={}", url), self.source_of("app_url")));
+        }
+
+        // Wayland / X11 backend
+        if let Some((platform, source)) = self.ozone_platform() {
+            flags.push(PlannedArg::new(platform.flag(), source));
         }
 
         // Incognito mode
         if self.incognito {
-            flags.push("--incognito".to_string());
+            flags.push(PlannedArg::new("--incognito", self.source_of("incognito")));
         }
 
         // GPU settings
         if self.disable_gpu && !self.headless {
-            flags.push("--disable-gpu".to_string());
+            flags.push(PlannedArg::new("--disable-gpu", self.source_of("disable_gpu")));
         }
 
         // Sandbox settings
         if self.no_sandbox {
-            flags.push("--no-sandbox".to_string());
+            flags.push(PlannedArg::new("--no-sandbox", self.source_of("no_sandbox")));
         }
+        flags.extend(self.get_host_flags());
 
         // Security settings
         if self.disable_web_security {
-            flags.push("--disable-web-security".to_string());
+            flags.push(PlannedArg::new("--disable-web-security", self.source_of("disable_web_security")));
         }
 
         if self.allow_running_insecure_content {
-            flags.push("--allow-running-insecure-content".to_string());
+            flags.push(PlannedArg::new("--allow-running-insecure-content", self.source_of("allow_running_insecure_content")));
         }
 
         if self.ignore_ssl_errors {
-            flags.push("--ignore-ssl-errors".to_string());
-            flags.push("--ignore-certificate-errors".to_string());
+            let source = self.source_of("ignore_ssl_errors");
+            flags.push(PlannedArg::new("--ignore-ssl-errors", source.clone()));
+            flags.push(PlannedArg::new("--ignore-certificate-errors", source));
+        }
+
+        if let Ok(ref hashes) = self.trusted_spki_hashes {
+            if !hashes.is_empty() {
+                flags.push(PlannedArg::new(
+                    format!("--ignore-certificate-errors-spki-list={}", hashes.join(",")),
+                    self.source_of("trusted_certificates"),
+                ));
+            }
         }
 
         // Extension and plugin settings
         if self.disable_extensions && !self.ignore_default_flags {
-            flags.push("--disable-extensions".to_string());
+            flags.push(PlannedArg::new("--disable-extensions", self.source_of("disable_extensions")));
+        }
+
+        if !self.loaded_extensions().is_empty() {
+            let paths: Vec<String> = self
+                .loaded_extensions()
+                .iter()
+                .map(|extension| extension.path.display().to_string())
+                .collect();
+            let source = self.source_of("extensions");
+            flags.push(PlannedArg::new(format!("--load-extension={}", paths.join(",")), source.clone()));
+            flags.push(PlannedArg::new(format!("--disable-extensions-except={}", paths.join(",")), source));
         }
 
         if self.disable_plugins {
-            flags.push("--disable-plugins".to_string());
+            flags.push(PlannedArg::new("--disable-plugins", self.source_of("disable_plugins")));
         }
 
         // Content settings
         if self.disable_images {
-            flags.push("--disable-images".to_string());
+            flags.push(PlannedArg::new("--disable-images", self.source_of("disable_images")));
         }
 
         if self.disable_javascript {
-            flags.push("--disable-javascript".to_string());
+            flags.push(PlannedArg::new("--disable-javascript", self.source_of("disable_javascript")));
         }
 
         // User agent
-        if let Some(ref ua) = self.user_agent {
-            flags.push(format!("--user-agent={}", ua));
+        if let Some((ua, source)) = self.user_agent() {
+            flags.push(PlannedArg::new(format!("--user-agent={}", ua), source));
+        }
+
+        // Logging to stderr, which goes to browser-err.log
+        if let Some(Ok((chromium_level, _))) = self.log_level.as_deref().map(log_level_settings) {
+            let source = self.source_of("log_level");
+            flags.push(PlannedArg::new("--enable-logging=stderr", source.clone()));
+            flags.push(match chromium_level {
+                Some(level) => PlannedArg::new(format!("--log-level={}", level), source),
+                None => PlannedArg::new("--v=1", source),
+            });
+        }
+
+        // UI language; Accept-Language comes from the intl.accept_languages pref
+        if let Some(ref locale) = self.locale {
+            flags.push(PlannedArg::new(format!("--lang={}", locale), self.source_of("locale")));
         }
 
         // Proxy settings
-        if let Some(ref proxy) = self.proxy_server {
-            flags.push(format!("--proxy-server={}", proxy));
+        if let Some((proxy, field)) = self.browser_proxy() {
+            flags.extend(proxy.chromium_flags().into_iter().map(|flag| PlannedArg::new(flag, self.source_of(field))));
         }
 
         // Host resolver rules
         if let Some(ref rules) = self.host_resolver_rules {
-            flags.push(format!("--host-resolver-rules={}", rules));
+            if !rules.is_empty() {
+                flags.push(PlannedArg::new(format!("--host-resolver-rules={}", rules), self.source_of("host_resolver_rules")));
+            }
         }
 
         // Additional custom flags
-        flags.extend(self.browser_flags.clone());
+        flags.extend(self.browser_flags.iter().map(|flag| PlannedArg::new(flag.clone(), FlagSource::User)));
 
         // Additional args from options
-        flags.extend(self.additional_args.clone());
+        flags.extend(self.additional_args.iter().map(|arg| PlannedArg::new(arg.clone(), FlagSource::User)));
 
         flags
     }
 
+    fn get_firefox_flags(&self) -> Vec<PlannedArg> {
+        let mut flags = vec![
+            // Remote Agent; port 0 lets Firefox pick one and print it to stderr
+            PlannedArg::new(format!("--remote-debugging-port={}", self.port), self.source_of("port")),
+            PlannedArg::new("-profile", self.source_of("user_data_dir")),
+            PlannedArg::new(self.user_data_dir.clone(), self.source_of("user_data_dir")),
+        ];
+
+        // Never hand the URL over to an already running Firefox
+        if !self.ignore_default_flags {
+            flags.push(PlannedArg::new("-no-remote", FlagSource::Default));
+        }
+
+        if self.headless {
+            flags.push(PlannedArg::new("-headless", self.source_of("headless")));
+        }
+
+        if let Some(((width, height), source)) = self.window_size() {
+            flags.push(PlannedArg::new("-width", source.clone()));
+            flags.push(PlannedArg::new(width.to_string(), source.clone()));
+            flags.push(PlannedArg::new("-height", source.clone()));
+            flags.push(PlannedArg::new(height.to_string(), source));
+        }
+
+        if self.kiosk {
+            flags.push(PlannedArg::new("-kiosk", self.source_of("kiosk")));
+        }
+
+        if self.incognito {
+            flags.push(PlannedArg::new("-private", self.source_of("incognito")));
+        }
+
+        flags.extend(self.browser_flags.iter().map(|flag| PlannedArg::new(flag.clone(), FlagSource::User)));
+        flags.extend(self.additional_args.iter().map(|arg| PlannedArg::new(arg.clone(), FlagSource::User)));
+
+        flags
+    }
+
+    /// Preferences merged into the Chromium profile; `prefs` wins over values
+    /// derived from other options
+    fn get_chromium_prefs(&self) -> HashMap<String, serde_json::Value> {
+        let mut prefs = HashMap::new();
+        if let Some(dir) = self.downloads_dir() {
+            prefs.insert("download.default_directory".to_string(), serde_json::json!(dir));
+            prefs.insert("download.prompt_for_download".to_string(), serde_json::json!(false));
+        }
+        if let Some(ref locale) = self.locale {
+            prefs.insert("intl.accept_languages".to_string(), serde_json::json!(locale::accept_languages(locale)));
+        }
+        prefs.extend(self.prefs.clone());
+        prefs
+    }
+
+    /// Preferences written to the Firefox profile's user.js; `prefs` wins over
+    /// values derived from other options, which win over the defaults
+    fn get_firefox_prefs(&self) -> HashMap<String, serde_json::Value> {
+        let mut prefs = HashMap::new();
+        if !self.ignore_default_flags {
+            for (key, value) in DEFAULT_FIREFOX_PREFS {
+                if let Ok(value) = serde_json::from_str(value) {
+                    prefs.insert(key.to_string(), value);
+                }
+            }
+        }
+
+        if let Some((ua, _)) = self.user_agent() {
+            prefs.insert("general.useragent.override".to_string(), serde_json::json!(ua));
+        }
+        if let Some(device) = self.device {
+            // Firefox reads the scale factor as a string pref
+            prefs.insert(
+                "layout.css.devPixelsPerPx".to_string(),
+                serde_json::json!(device.device_scale_factor.to_string()),
+            );
+            if device.touch {
+                prefs.insert("dom.w3c_touch_events.enabled".to_string(), serde_json::json!(1));
+            }
+        }
+        if let Some(ref locale) = self.locale {
+            prefs.insert("intl.accept_languages".to_string(), serde_json::json!(locale::accept_languages(locale)));
+            prefs.insert("intl.locale.requested".to_string(), serde_json::json!(locale));
+        }
+        if let Some(Ok((_, firefox_level))) = self.log_level.as_deref().map(log_level_settings) {
+            prefs.insert("remote.log.level".to_string(), serde_json::json!(firefox_level));
+        }
+        if self.disable_javascript {
+            prefs.insert("javascript.enabled".to_string(), serde_json::json!(false));
+        }
+        if self.disable_images {
+            prefs.insert("permissions.default.image".to_string(), serde_json::json!(2));
+        }
+        if let Some((proxy, _)) = self.browser_proxy() {
+            prefs.extend(proxy.firefox_prefs());
+        }
+        if let Some(dir) = self.downloads_dir() {
+            prefs.insert("browser.download.dir".to_string(), serde_json::json!(dir));
+            prefs.insert("browser.download.folderList".to_string(), serde_json::json!(2));
+        }
+
+        prefs.extend(self.prefs.clone());
+        prefs
+    }
+
     fn cleanup(&self) {
         let dir = PathBuf::from(self.user_data_dir.clone());
         if dir.exists() {
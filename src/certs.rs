@@ -0,0 +1,90 @@
+//! SPKI hashes of PEM certificates, for `--ignore-certificate-errors-spki-list`
+
+use crate::utils::{base64_decode, base64_encode, sha256};
+use std::fs;
+use std::path::Path;
+
+const PEM_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_END: &str = "-----END CERTIFICATE-----";
+
+const DER_SEQUENCE: u8 = 0x30;
+/// `[0] EXPLICIT` tag of the optional version field in `TBSCertificate`
+const DER_VERSION: u8 = 0xa0;
+
+/// Base64 SHA-256 hashes of the public keys of every certificate in the PEM file at `path`
+pub fn spki_hashes(path: &Path) -> Result<Vec<String>, String> {
+    let pem = fs::read_to_string(path).map_err(|e| format!("Failed to read certificate {}: {}", path.display(), e))?;
+    let certificates = pem_certificates(&pem).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if certificates.is_empty() {
+        return Err(format!("No PEM certificate found in {}", path.display()));
+    }
+    certificates
+        .iter()
+        .map(|der| spki_sha256(der).map_err(|e| format!("{}: {}", path.display(), e)))
+        .collect()
+}
+
+/// DER bytes of each `CERTIFICATE` block in `pem`
+pub fn pem_certificates(pem: &str) -> Result<Vec<Vec<u8>>, String> {
+    let mut certificates = Vec::new();
+    let mut rest = pem;
+    while let Some(start) = rest.find(PEM_BEGIN) {
+        let body = &rest[start + PEM_BEGIN.len()..];
+        let end = body.find(PEM_END).ok_or("Unterminated PEM certificate")?;
+        let base64: String = body[..end].chars().filter(|c| !c.is_whitespace()).collect();
+        certificates.push(base64_decode(&base64).ok_or("Invalid base64 in PEM certificate")?);
+        rest = &body[end + PEM_END.len()..];
+    }
+    Ok(certificates)
+}
+
+/// Base64 SHA-256 of the `SubjectPublicKeyInfo` of a DER certificate
+pub fn spki_sha256(certificate: &[u8]) -> Result<String, String> {
+    let invalid = || "Invalid DER certificate".to_string();
+    // Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signature }
+    let (certificate, _) = der_element(certificate, DER_SEQUENCE).ok_or_else(invalid)?;
+    let (tbs, _) = der_element(der_contents(certificate).ok_or_else(invalid)?, DER_SEQUENCE).ok_or_else(invalid)?;
+
+    // TBSCertificate ::= SEQUENCE { [0] version OPTIONAL, serialNumber, signature,
+    //                               issuer, validity, subject, subjectPublicKeyInfo, ... }
+    let mut fields = der_contents(tbs).ok_or_else(invalid)?;
+    if fields.first() == Some(&DER_VERSION) {
+        fields = der_next(fields).ok_or_else(invalid)?.1;
+    }
+    for _ in 0..5 {
+        fields = der_next(fields).ok_or_else(invalid)?.1;
+    }
+    let (spki, _) = der_element(fields, DER_SEQUENCE).ok_or_else(invalid)?;
+    Ok(base64_encode(&sha256(spki)))
+}
+
+/// Split the first element (tag, length and contents) off `data`
+fn der_next(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let first_length = *data.get(1)?;
+    let (header, length) = if first_length < 0x80 {
+        (2, first_length as usize)
+    } else {
+        let count = (first_length & 0x7f) as usize;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let length = data.get(2..2 + count)?.iter().fold(0usize, |n, &b| n << 8 | b as usize);
+        (2 + count, length)
+    };
+    let end = header.checked_add(length)?;
+    (end <= data.len()).then(|| data.split_at(end))
+}
+
+/// Like `der_next`, but the element must have tag `tag`
+fn der_element(data: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    (data.first() == Some(&tag)).then(|| der_next(data)).flatten()
+}
+
+/// The contents of a single element, without its tag and length
+fn der_contents(element: &[u8]) -> Option<&[u8]> {
+    let header = match *element.get(1)? {
+        length if length < 0x80 => 2,
+        length => 2 + (length & 0x7f) as usize,
+    };
+    element.get(header..)
+}
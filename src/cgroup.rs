@@ -0,0 +1,337 @@
+//! cgroup v2 resource limits for the browser process tree
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// `cpu.max` period in microseconds
+const CPU_PERIOD: u64 = 100_000;
+const DRAIN_POLLS: u32 = 40;
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Leaf cgroup the launcher's own processes move into, so controllers can be
+/// enabled for the browser cgroups next to it
+const LAUNCHER_CGROUP: &str = "launcher";
+
+/// Limits applied to the cgroup holding the browser and all of its children
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResourceLimits {
+    /// `memory.max` in bytes
+    pub memory_max: Option<u64>,
+    /// CPU time as a number of CPUs, e.g. `1.5`; written to `cpu.max`
+    pub cpu_quota: Option<f64>,
+    /// `pids.max`
+    pub pids_max: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// The cgroup controllers these limits need
+    fn controllers(&self) -> Vec<&'static str> {
+        let mut controllers = Vec::new();
+        if self.memory_max.is_some() {
+            controllers.push("memory");
+        }
+        if self.cpu_quota.is_some() {
+            controllers.push("cpu");
+        }
+        if self.pids_max.is_some() {
+            controllers.push("pids");
+        }
+        controllers
+    }
+
+    /// The interface files and values to write
+    fn files(&self) -> Vec<(&'static str, String)> {
+        let mut files = Vec::new();
+        if let Some(memory) = self.memory_max {
+            files.push(("memory.max", memory.to_string()));
+        }
+        if let Some(cpus) = self.cpu_quota {
+            let quota = (cpus * CPU_PERIOD as f64).round().max(1000.0) as u64;
+            files.push(("cpu.max", format!("{} {}", quota, CPU_PERIOD)));
+        }
+        if let Some(pids) = self.pids_max {
+            files.push(("pids.max", pids.to_string()));
+        }
+        files
+    }
+}
+
+impl fmt::Display for ResourceLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let files: Vec<String> = self.files().iter().map(|(file, value)| format!("{}={}", file, value)).collect();
+        if files.is_empty() {
+            write!(f, "no limits (accounting only)")
+        } else {
+            write!(f, "{}", files.join(", "))
+        }
+    }
+}
+
+impl FromStr for ResourceLimits {
+    type Err = String;
+
+    /// Parse `memory=2G,cpu=1.5,pids=512`; any part may be left out
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut limits = ResourceLimits::default();
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid resource limit '{}', expected KEY=VALUE", part))?;
+            match key.trim() {
+                "memory" => limits.memory_max = Some(parse_bytes(value.trim())?),
+                "cpu" => {
+                    let cpus = value.trim().parse::<f64>().map_err(|_| format!("Invalid CPU quota '{}'", value))?;
+                    if cpus <= 0.0 {
+                        return Err(format!("Invalid CPU quota '{}'", value));
+                    }
+                    limits.cpu_quota = Some(cpus);
+                }
+                "pids" => {
+                    limits.pids_max = Some(value.trim().parse().map_err(|_| format!("Invalid pids limit '{}'", value))?)
+                }
+                other => return Err(format!("Unknown resource limit '{}' (expected memory, cpu or pids)", other)),
+            }
+        }
+        Ok(limits)
+    }
+}
+
+/// Parse a byte size such as `512M` or `2G`
+fn parse_bytes(value: &str) -> Result<u64, String> {
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (digits, unit) = value.split_at(split);
+    let multiplier: u64 = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return Err(format!("Invalid memory size '{}'", value)),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("Invalid memory size '{}'", value))
+}
+
+/// Usage counters read from the cgroup; missing controllers leave fields empty
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResourceUsage {
+    pub memory_current: Option<u64>,
+    pub memory_peak: Option<u64>,
+    pub cpu_usage_usec: Option<u64>,
+    pub pids_current: Option<u64>,
+}
+
+/// A child cgroup created for one browser; killed and removed when dropped
+#[derive(Debug)]
+pub struct Cgroup {
+    pub path: PathBuf,
+    removed: bool,
+}
+
+impl Cgroup {
+    /// Create a cgroup for the browser in the current process's cgroup v2 and apply `limits`
+    pub fn create(limits: &ResourceLimits) -> Result<Self, String> {
+        Self::create_in(&current_cgroup_dir()?, limits)
+    }
+
+    /// Create a cgroup for the browser next to the launcher, given the
+    /// launcher's cgroup directory `current`, and apply `limits`
+    pub fn create_in(current: &Path, limits: &ResourceLimits) -> Result<Self, String> {
+        let parent = enable_controllers(current, &limits.controllers())?;
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        let path = parent.join(format!("browser_launcher-{}-{}", std::process::id(), nanos));
+        fs::create_dir(&path).map_err(|e| format!("Failed to create cgroup {}: {}", path.display(), e))?;
+        let cgroup = Self { path, removed: false };
+
+        for (file, value) in limits.files() {
+            fs::write(cgroup.path.join(file), &value)
+                .map_err(|e| format!("Failed to set {} to {}: {}", file, value, e))?;
+        }
+        Ok(cgroup)
+    }
+
+    /// Move a process into the cgroup; its future children stay in it
+    pub fn add_process(&self, pid: u32) -> Result<(), String> {
+        fs::write(self.path.join("cgroup.procs"), pid.to_string())
+            .map_err(|e| format!("Failed to move PID {} into {}: {}", pid, self.path.display(), e))
+    }
+
+    /// Open `cgroup.procs` for writing; writing `0` to it moves the writer.
+    ///
+    /// Opened before forking so the child can join the cgroup before `exec`
+    /// without allocating.
+    pub fn open_procs(&self) -> Result<File, String> {
+        OpenOptions::new()
+            .write(true)
+            .open(self.path.join("cgroup.procs"))
+            .map_err(|e| format!("Failed to open {}: {}", self.path.join("cgroup.procs").display(), e))
+    }
+
+    /// PIDs currently in the cgroup
+    pub fn pids(&self) -> Vec<u32> {
+        fs::read_to_string(self.path.join("cgroup.procs"))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .collect()
+    }
+
+    /// Kill every process in the cgroup and wait for it to drain
+    pub fn kill(&self) -> Result<(), String> {
+        // cgroup.kill needs Linux 5.14; fall back to signalling each PID
+        if fs::write(self.path.join("cgroup.kill"), "1").is_err() {
+            for pid in self.pids() {
+                kill_process(pid)?;
+            }
+        }
+        for _ in 0..DRAIN_POLLS {
+            if self.pids().is_empty() {
+                return Ok(());
+            }
+            thread::sleep(DRAIN_POLL_INTERVAL);
+        }
+        Err(format!("Processes are still running in {}", self.path.display()))
+    }
+
+    /// Current usage counters
+    pub fn usage(&self) -> ResourceUsage {
+        let read = |file: &str| -> Option<u64> { fs::read_to_string(self.path.join(file)).ok()?.trim().parse().ok() };
+        let cpu_usage_usec = fs::read_to_string(self.path.join("cpu.stat")).ok().and_then(|stat| {
+            stat.lines()
+                .find_map(|line| line.strip_prefix("usage_usec "))
+                .and_then(|value| value.trim().parse().ok())
+        });
+        ResourceUsage {
+            memory_current: read("memory.current"),
+            memory_peak: read("memory.peak"),
+            cpu_usage_usec,
+            pids_current: read("pids.current"),
+        }
+    }
+
+    /// Kill anything left in the cgroup and remove it
+    pub fn remove(&mut self) {
+        if self.removed {
+            return;
+        }
+        let _ = self.kill();
+        if fs::remove_dir(&self.path).is_ok() || !self.path.exists() {
+            self.removed = true;
+        }
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+/// SIGKILL `pid`; a process that already exited is not an error
+#[cfg(unix)]
+fn kill_process(pid: u32) -> Result<(), String> {
+    match crate::utils::send_signal(pid, libc::SIGKILL) {
+        Err(e) if e.raw_os_error() != Some(libc::ESRCH) => Err(format!("Failed to kill PID {}: {}", pid, e)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_process(pid: u32) -> Result<(), String> {
+    Err(format!("Cannot kill PID {}: resource_limits require Linux", pid))
+}
+
+/// Enable `controllers` for the children of the cgroup the browser cgroups go
+/// in, and return that cgroup.
+///
+/// cgroup v2 only lets a cgroup without processes of its own hand controllers
+/// to its children, so the launcher first moves into a `launcher` leaf and the
+/// browser cgroups become its siblings. This fails when `current` holds any
+/// other process. When the launcher already runs in that leaf, its parent is used.
+fn enable_controllers(current: &Path, controllers: &[&str]) -> Result<PathBuf, String> {
+    let parent = match (current.file_name(), current.parent()) {
+        (Some(name), Some(parent)) if name == LAUNCHER_CGROUP => parent.to_path_buf(),
+        _ => current.to_path_buf(),
+    };
+    let read_list = |file: &str| fs::read_to_string(parent.join(file)).unwrap_or_default();
+    let enabled = read_list("cgroup.subtree_control");
+    let missing: Vec<&str> = controllers
+        .iter()
+        .copied()
+        .filter(|controller| !enabled.split_whitespace().any(|c| c == *controller))
+        .collect();
+    if missing.is_empty() {
+        return Ok(parent);
+    }
+
+    let available = read_list("cgroup.controllers");
+    let undelegated = missing
+        .iter()
+        .find(|controller| !available.split_whitespace().any(|c| c == **controller));
+    if let Some(controller) = undelegated {
+        return Err(format!(
+            "The {} controller is not delegated to {}; run under a delegated cgroup, e.g. \
+             `systemd-run --user --scope -p Delegate=yes`",
+            controller,
+            parent.display()
+        ));
+    }
+
+    let procs = read_list("cgroup.procs");
+    if !procs.trim().is_empty() {
+        // Moving other processes would pull them out of the place their owner
+        // put them, and ones forked meanwhile would be missed anyway
+        let own_pid = std::process::id().to_string();
+        if procs.split_whitespace().any(|pid| pid != own_pid) {
+            return Err(format!(
+                "Cannot enable {} in {}: it holds processes other than the launcher; \
+                 run the launcher in a cgroup of its own, e.g. `systemd-run --user --scope -p Delegate=yes`",
+                missing.join(", "),
+                parent.display()
+            ));
+        }
+        let leaf = parent.join(LAUNCHER_CGROUP);
+        if !leaf.is_dir() {
+            fs::create_dir(&leaf).map_err(|e| format!("Failed to create cgroup {}: {}", leaf.display(), e))?;
+        }
+        fs::write(leaf.join("cgroup.procs"), &own_pid)
+            .map_err(|e| format!("Failed to move the launcher into {}: {}", leaf.display(), e))?;
+    }
+
+    let change: Vec<String> = missing.iter().map(|controller| format!("+{}", controller)).collect();
+    fs::write(parent.join("cgroup.subtree_control"), change.join(" "))
+        .map_err(|e| format!("Failed to enable {} in {}: {}", missing.join(", "), parent.display(), e))?;
+    Ok(parent)
+}
+
+/// Directory of the current process's cgroup in the cgroup v2 hierarchy
+fn current_cgroup_dir() -> Result<PathBuf, String> {
+    if !cfg!(target_os = "linux") {
+        return Err("resource_limits require Linux with cgroup v2".to_string());
+    }
+    let mounts = fs::read_to_string("/proc/mounts").map_err(|e| e.to_string())?;
+    let root = mounts
+        .lines()
+        .find_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            (fields.get(2) == Some(&"cgroup2")).then(|| fields.get(1).map(PathBuf::from)).flatten()
+        })
+        .ok_or("resource_limits require a mounted cgroup v2 hierarchy")?;
+
+    // The unified hierarchy is the "0::" line
+    let cgroups = fs::read_to_string("/proc/self/cgroup").map_err(|e| e.to_string())?;
+    let relative = cgroups
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .ok_or("Could not find the current cgroup v2 in /proc/self/cgroup")?;
+    Ok(root.join(Path::new(relative.trim()).strip_prefix("/").unwrap_or(Path::new(""))))
+}
@@ -1,5 +1,88 @@
-use clap::{Parser, ValueEnum};
+use crate::browser::{BrowserType, Protocol};
+use crate::browser_launcher::Options;
+use crate::cgroup::ResourceLimits;
+use crate::devices::DevicePreset;
+use crate::display::OzonePlatform;
+use crate::host::SandboxPolicy;
+use crate::native_messaging::NativeMessagingHost;
+use crate::proxy::ProxyConfig;
+use crate::resolver::HostResolverRules;
+use crate::sched::parse_cpu_list;
+use crate::wrapper::SandboxWrapper;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
 
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+
+    /// Output format; `json` emits newline-delimited JSON events
+    #[arg(long, value_enum, global = true, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// Launch options, used when no subcommand is given
+    #[command(flatten)]
+    pub launch: Args,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Launch a browser and wait for it to exit (the default)
+    Launch(Args),
+    /// List browsers discovered on this system
+    Find,
+    /// Print the version of one browser
+    Version(VersionArgs),
+    /// Print the command line a launch would use, without starting anything
+    Flags(Args),
+    /// Stop browser instances recorded by previous launches
+    Kill(KillArgs),
+    /// List open tabs of a running browser instance
+    Targets(TargetsArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct VersionArgs {
+    /// Browser type to query
+    #[arg(long)]
+    pub browser: Option<BrowserTypeArg>,
+
+    /// Browser executable to query instead of a discovered one
+    #[arg(long)]
+    pub browser_path: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct KillArgs {
+    /// Only stop the instance with this PID (defaults to all recorded instances)
+    #[arg(long)]
+    pub pid: Option<u32>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct TargetsArgs {
+    /// Remote debugging port of the instance
+    #[arg(long, conflicts_with = "pid")]
+    pub port: Option<u16>,
+
+    /// PID of a recorded instance (defaults to the only running one)
+    #[arg(long)]
+    pub pid: Option<u32>,
+}
+
+// Switches are `Option<bool>` so that e.g. `--headless=false` can turn off a
+// value from BROWSER_LAUNCHER_HEADLESS or the config file; a bare `--headless` is true
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
@@ -7,6 +90,10 @@ pub struct Args {
     #[arg(long)]
     pub starting_url: Option<String>,
 
+    /// Open this URL in its own tab (repeatable, replaces --starting-url)
+    #[arg(long = "url", value_name = "URL")]
+    pub urls: Vec<String>,
+
     /// Sets browser flags (comma-separated)
     #[arg(long)]
     pub browser_flags: Option<String>,
@@ -15,69 +102,172 @@ pub struct Args {
     #[arg(long)]
     pub port: Option<u16>,
 
-    /// Browser type to launch
-    #[arg(long, value_enum)]
+    /// Browser type to launch (chrome, chrome-canary, chromium, edge, brave, opera, vivaldi,
+    /// firefox, firefox-esr, firefox-nightly, firefox-developer-edition or custom:PATH)
+    #[arg(long)]
     pub browser: Option<BrowserTypeArg>,
 
-    /// Run in headless mode
+    /// Protocol to wait for during readiness (cdp or bidi; defaults to the browser's own, bidi is Firefox only)
+    #[arg(long)]
+    pub protocol: Option<Protocol>,
+
+    /// Browser executable to launch instead of a discovered one
     #[arg(long)]
-    pub headless: bool,
+    pub browser_path: Option<String>,
+
+    /// Run in headless mode
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub headless: Option<bool>,
 
     /// Window size (format: WIDTHxHEIGHT)
     #[arg(long)]
     pub window_size: Option<String>,
 
-    /// Run in incognito mode
+    /// Emulate a bundled device: user agent, window size, scale factor and touch
+    /// (e.g. pixel-7, iphone-14, ipad)
+    #[arg(long, value_name = "NAME")]
+    pub device: Option<DevicePreset>,
+
+    /// Window position (format: X,Y)
+    #[arg(long, value_name = "X,Y", allow_hyphen_values = true)]
+    pub window_position: Option<String>,
+
+    /// Start with a maximized window
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub start_maximized: Option<bool>,
+
+    /// Start in fullscreen
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub fullscreen: Option<bool>,
+
+    /// Run in kiosk mode: fullscreen without browser UI
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub kiosk: Option<bool>,
+
+    /// Open URL in an app window without tabs or address bar
+    #[arg(long = "app", value_name = "URL")]
+    pub app_url: Option<String>,
+
+    /// Start an Xvfb display for a headful browser (Linux)
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub virtual_display: Option<bool>,
+
+    /// Xvfb screen size (format: WIDTHxHEIGHT, defaults to --window-size)
     #[arg(long)]
-    pub incognito: bool,
+    pub virtual_display_size: Option<String>,
 
-    /// Disable GPU acceleration
+    /// Ozone platform on Linux (auto, wayland or x11; detected from the session by default)
+    #[arg(long)]
+    pub ozone_platform: Option<OzonePlatform>,
+
+    /// Run the browser in its own cgroup v2 (format: memory=2G,cpu=1.5,pids=512)
+    #[arg(long)]
+    pub resource_limits: Option<ResourceLimits>,
+
+    /// Scheduling priority of the browser, -20 to 19
+    #[arg(long, allow_negative_numbers = true)]
+    pub nice: Option<i32>,
+
+    /// CPUs the browser may run on (format: 0-3,6)
     #[arg(long)]
-    pub disable_gpu: bool,
+    pub cpu_affinity: Option<String>,
+
+    /// OOM killer adjustment of the browser, -1000 to 1000
+    #[arg(long, allow_negative_numbers = true)]
+    pub oom_score_adj: Option<i32>,
+
+    /// Command to run the browser under, split on whitespace (e.g. "strace -f -o trace.txt")
+    #[arg(long, value_name = "COMMAND")]
+    pub wrapper: Option<String>,
+
+    /// Run in incognito mode
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub incognito: Option<bool>,
+
+    /// Disable GPU acceleration
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub disable_gpu: Option<bool>,
 
     /// Disable sandbox (use with caution)
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub no_sandbox: Option<bool>,
+
+    /// Reaction to root, container and small /dev/shm hosts (auto, strict or off)
     #[arg(long)]
-    pub no_sandbox: bool,
+    pub sandbox_policy: Option<SandboxPolicy>,
+
+    /// Run the browser inside a sandbox: bwrap[:no-network,downloads=DIR] or a prefix command such as "firejail --private"
+    #[arg(long, value_name = "SPEC")]
+    pub sandbox_wrapper: Option<SandboxWrapper>,
 
     /// Disable web security
-    #[arg(long)]
-    pub disable_web_security: bool,
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub disable_web_security: Option<bool>,
 
     /// Allow running insecure content
-    #[arg(long)]
-    pub allow_insecure_content: bool,
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub allow_insecure_content: Option<bool>,
 
     /// Ignore SSL/HTTPS errors
-    #[arg(long)]
-    pub ignore_ssl_errors: bool,
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub ignore_ssl_errors: Option<bool>,
+
+    /// Accept certificate errors only for the keys in this PEM file (repeatable)
+    #[arg(long = "trust-cert", value_name = "PATH")]
+    pub trust_certs: Vec<PathBuf>,
 
     /// Disable browser extensions
-    #[arg(long)]
-    pub disable_extensions: bool,
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub disable_extensions: Option<bool>,
+
+    /// Load an unpacked extension from this directory (repeatable)
+    #[arg(long = "extension", value_name = "DIR")]
+    pub extensions: Vec<PathBuf>,
+
+    /// Install a native messaging host manifest for the loaded extensions
+    /// (format: [user:|profile:]NAME=PATH, repeatable)
+    #[arg(long = "native-messaging-host", value_name = "NAME=PATH")]
+    pub native_messaging_hosts: Vec<NativeMessagingHost>,
 
     /// Disable plugins
-    #[arg(long)]
-    pub disable_plugins: bool,
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub disable_plugins: Option<bool>,
 
     /// Disable images
-    #[arg(long)]
-    pub disable_images: bool,
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub disable_images: Option<bool>,
 
     /// Disable JavaScript
-    #[arg(long)]
-    pub disable_javascript: bool,
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub disable_javascript: Option<bool>,
 
     /// Set custom user agent
     #[arg(long)]
     pub user_agent: Option<String>,
 
+    /// Browser language and Accept-Language (e.g. de-DE)
+    #[arg(long, value_name = "LOCALE")]
+    pub locale: Option<String>,
+
+    /// IANA timezone for the browser (e.g. America/New_York)
+    #[arg(long, value_name = "ZONE")]
+    pub timezone: Option<String>,
+
     /// Set proxy server
     #[arg(long)]
     pub proxy_server: Option<String>,
 
+    /// Proxy settings: direct, system, pac:URL or rules such as "http=proxy:3128;https=proxy:3129"
+    #[arg(long, value_name = "SPEC")]
+    pub proxy: Option<ProxyConfig>,
+
+    /// Hosts that bypass the proxy (comma-separated, e.g. "localhost,*.internal")
+    #[arg(long, value_name = "HOSTS")]
+    pub proxy_bypass_list: Option<String>,
+
     /// Set host resolver rules
     #[arg(long)]
-    pub host_resolver_rules: Option<String>,
+    pub host_resolver_rules: Option<HostResolverRules>,
 
     /// User data directory
     #[arg(long)]
@@ -86,16 +276,227 @@ pub struct Args {
     /// Additional browser arguments (comma-separated)
     #[arg(long)]
     pub additional_args: Option<String>,
+
+    /// Browser preference (format: KEY=JSON, repeatable)
+    #[arg(long = "pref", value_name = "KEY=JSON")]
+    pub prefs: Vec<String>,
+
+    /// Environment variable for the browser process (format: KEY=VALUE, repeatable)
+    #[arg(long = "env", value_name = "KEY=VALUE")]
+    pub env_vars: Vec<String>,
+
+    /// Browser logging to browser-err.log (verbose, info, warning, error, silent)
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Do not add the default flags
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub ignore_default_flags: Option<bool>,
+
+    /// On SIGINT, shut the browser down and remove its instance record
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true", value_name = "BOOL")]
+    pub handle_sigint: Option<bool>,
+
+    /// Milliseconds between DevTools readiness checks
+    #[arg(long, value_name = "MS")]
+    pub connection_poll_interval: Option<u64>,
+
+    /// Number of DevTools readiness checks before giving up
+    #[arg(long)]
+    pub max_connection_retries: Option<u32>,
+
+    /// Print the launch plan, with the source of every flag, instead of launching
+    #[arg(long)]
+    pub dry_run: bool,
 }
 
-#[derive(Clone, Debug, ValueEnum)]
+impl Args {
+    /// Convert the parsed arguments into launcher `Options`.
+    ///
+    /// Switches that were not passed stay `None`, so environment variables
+    /// and the config file can still provide them.
+    pub fn into_options(self) -> Result<Options, String> {
+        let window_size = match self.window_size {
+            Some(ref size) => Some(
+                parse_window_size(size)
+                    .ok_or("Invalid window size format. Expected WIDTHxHEIGHT (e.g., 1920x1080)")?,
+            ),
+            None => None,
+        };
+        let window_position = match self.window_position {
+            Some(ref position) => Some(
+                parse_window_position(position)
+                    .ok_or("Invalid window position format. Expected X,Y (e.g., 1920,0)")?,
+            ),
+            None => None,
+        };
+        let virtual_display_size = match self.virtual_display_size {
+            Some(ref size) => Some(
+                parse_window_size(size)
+                    .ok_or("Invalid virtual display size format. Expected WIDTHxHEIGHT (e.g., 1920x1080)")?,
+            ),
+            None => None,
+        };
+
+        // A bypass list turns the proxy_server shorthand into a full proxy configuration
+        let (proxy, proxy_server) = match self.proxy_bypass_list {
+            Some(ref hosts) => {
+                let proxy = match (self.proxy, self.proxy_server) {
+                    (Some(proxy), None) => proxy,
+                    (None, Some(server)) => server.parse::<ProxyConfig>()?,
+                    (Some(_), Some(_)) => return Err("Use either --proxy or --proxy-server, not both".to_string()),
+                    (None, None) => return Err("--proxy-bypass-list needs --proxy or --proxy-server".to_string()),
+                };
+                let hosts = split_list(hosts).iter().map(|host| host.trim().to_string()).collect();
+                (Some(proxy.with_bypass_list(hosts)?), None)
+            }
+            None => (self.proxy, self.proxy_server),
+        };
+
+        let mut prefs = HashMap::new();
+        for pref in &self.prefs {
+            let (key, value) = parse_key_value(pref)?;
+            // Values that are not valid JSON are taken as plain strings
+            let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+            prefs.insert(key.to_string(), value);
+        }
+
+        let mut env_vars = HashMap::new();
+        for var in &self.env_vars {
+            let (key, value) = parse_key_value(var)?;
+            env_vars.insert(key.to_string(), value.to_string());
+        }
+
+        Ok(Options {
+            starting_url: self.starting_url,
+            starting_urls: (!self.urls.is_empty()).then_some(self.urls),
+            browser_flags: self.browser_flags.map(|flags| split_list(&flags)),
+            prefs: (!prefs.is_empty()).then_some(prefs),
+            port: self.port,
+            handle_sigint: self.handle_sigint,
+            browser_path: self.browser_path,
+            user_data_dir: self.user_data_dir,
+            log_level: self.log_level,
+            ignore_default_flags: self.ignore_default_flags,
+            connection_poll_interval: self.connection_poll_interval,
+            max_connection_retries: self.max_connection_retries,
+            env_vars: (!env_vars.is_empty()).then_some(env_vars),
+            browser: self.browser.map(BrowserType::from),
+            protocol: self.protocol,
+            headless: self.headless,
+            window_size,
+            device: self.device,
+            window_position,
+            start_maximized: self.start_maximized,
+            fullscreen: self.fullscreen,
+            kiosk: self.kiosk,
+            app_url: self.app_url,
+            virtual_display: self.virtual_display,
+            virtual_display_size,
+            ozone_platform: self.ozone_platform,
+            resource_limits: self.resource_limits,
+            nice: self.nice,
+            cpu_affinity: self.cpu_affinity.as_deref().map(parse_cpu_list).transpose()?,
+            oom_score_adj: self.oom_score_adj,
+            wrapper: self.wrapper.map(|wrapper| wrapper.split_whitespace().map(String::from).collect()),
+            incognito: self.incognito,
+            disable_gpu: self.disable_gpu,
+            no_sandbox: self.no_sandbox,
+            sandbox_policy: self.sandbox_policy,
+            sandbox_wrapper: self.sandbox_wrapper,
+            disable_web_security: self.disable_web_security,
+            allow_running_insecure_content: self.allow_insecure_content,
+            ignore_ssl_errors: self.ignore_ssl_errors,
+            trusted_certificates: (!self.trust_certs.is_empty()).then_some(self.trust_certs),
+            disable_extensions: self.disable_extensions,
+            extensions: (!self.extensions.is_empty()).then_some(self.extensions),
+            native_messaging_hosts: (!self.native_messaging_hosts.is_empty()).then_some(self.native_messaging_hosts),
+            disable_plugins: self.disable_plugins,
+            disable_images: self.disable_images,
+            disable_javascript: self.disable_javascript,
+            user_agent: self.user_agent,
+            locale: self.locale,
+            timezone: self.timezone,
+            proxy_server,
+            proxy,
+            host_resolver_rules: self.host_resolver_rules,
+            additional_args: self.additional_args.map(|args| split_list(&args)),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum BrowserTypeArg {
     Chrome,
+    ChromeCanary,
     Chromium,
     Edge,
     Brave,
     Opera,
     Vivaldi,
+    Firefox,
+    FirefoxEsr,
+    FirefoxNightly,
+    FirefoxDeveloperEdition,
+    Custom(String),
+}
+
+impl FromStr for BrowserTypeArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse::<BrowserType>()? {
+            BrowserType::Chrome => BrowserTypeArg::Chrome,
+            BrowserType::ChromeCanary => BrowserTypeArg::ChromeCanary,
+            BrowserType::Chromium => BrowserTypeArg::Chromium,
+            BrowserType::Edge => BrowserTypeArg::Edge,
+            BrowserType::Brave => BrowserTypeArg::Brave,
+            BrowserType::Opera => BrowserTypeArg::Opera,
+            BrowserType::Vivaldi => BrowserTypeArg::Vivaldi,
+            BrowserType::Firefox => BrowserTypeArg::Firefox,
+            BrowserType::FirefoxEsr => BrowserTypeArg::FirefoxEsr,
+            BrowserType::FirefoxNightly => BrowserTypeArg::FirefoxNightly,
+            BrowserType::FirefoxDeveloperEdition => BrowserTypeArg::FirefoxDeveloperEdition,
+            BrowserType::Custom(path) => BrowserTypeArg::Custom(path),
+        })
+    }
+}
+
+impl From<BrowserTypeArg> for BrowserType {
+    fn from(arg: BrowserTypeArg) -> Self {
+        match arg {
+            BrowserTypeArg::Chrome => BrowserType::Chrome,
+            BrowserTypeArg::ChromeCanary => BrowserType::ChromeCanary,
+            BrowserTypeArg::Chromium => BrowserType::Chromium,
+            BrowserTypeArg::Edge => BrowserType::Edge,
+            BrowserTypeArg::Brave => BrowserType::Brave,
+            BrowserTypeArg::Opera => BrowserType::Opera,
+            BrowserTypeArg::Vivaldi => BrowserType::Vivaldi,
+            BrowserTypeArg::Firefox => BrowserType::Firefox,
+            BrowserTypeArg::FirefoxEsr => BrowserType::FirefoxEsr,
+            BrowserTypeArg::FirefoxNightly => BrowserType::FirefoxNightly,
+            BrowserTypeArg::FirefoxDeveloperEdition => BrowserType::FirefoxDeveloperEdition,
+            BrowserTypeArg::Custom(path) => BrowserType::Custom(path),
+        }
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value.split(',').map(String::from).collect()
+}
+
+/// Split a `KEY=VALUE` argument
+pub fn parse_key_value(arg: &str) -> Result<(&str, &str), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key, value)),
+        _ => Err(format!("Expected KEY=VALUE, got '{}'", arg)),
+    }
+}
+
+/// Parse `X,Y`; coordinates may be negative
+pub fn parse_window_position(position: &str) -> Option<(i32, i32)> {
+    let (x, y) = position.split_once(',')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
 }
 
 pub fn parse_window_size(size: &str) -> Option<(u32, u32)> {
@@ -0,0 +1,511 @@
+//! Layered configuration for `Options`.
+//!
+//! Every `Options` field can be supplied from three places besides code.
+//! Precedence, highest first:
+//!
+//! 1. fields set explicitly on the `Options` passed to the launcher
+//! 2. `BROWSER_LAUNCHER_<FIELD>` environment variables (e.g. `BROWSER_LAUNCHER_HEADLESS=1`)
+//! 3. the JSON config file named by `BROWSER_LAUNCHER_CONFIG`
+//! 4. the launcher's built-in defaults
+//!
+//! Environment values are plain strings: booleans accept `1/true/yes/on` and
+//! `0/false/no/off`, lists are comma-separated, maps (`prefs`, `env_vars`)
+//! are JSON objects and `window_size` uses `WIDTHxHEIGHT`. The config file
+//! is a JSON object keyed by field name.
+
+use crate::browser::{BrowserType, Protocol};
+use crate::browser_launcher::Options;
+use crate::cgroup::ResourceLimits;
+use crate::cli::{parse_window_position, parse_window_size};
+use crate::devices::DevicePreset;
+use crate::display::OzonePlatform;
+use crate::host::SandboxPolicy;
+use crate::native_messaging::NativeMessagingHost;
+use crate::plan::FlagSource;
+use crate::proxy::ProxyConfig;
+use crate::resolver::HostResolverRules;
+use crate::sched::parse_cpu_list;
+use crate::wrapper::SandboxWrapper;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Prefix shared by all option environment variables
+pub const ENV_PREFIX: &str = "BROWSER_LAUNCHER_";
+
+/// Environment variable naming the JSON config file
+pub const CONFIG_ENV_VAR: &str = "BROWSER_LAUNCHER_CONFIG";
+
+/// Legacy variable, still honoured as a fallback for `BROWSER_LAUNCHER_HEADLESS`
+pub const LEGACY_HEADLESS_ENV_VAR: &str = "HEADLESS";
+
+/// Environment variable name for an option field
+pub fn env_var_name(field: &str) -> String {
+    format!("{}{}", ENV_PREFIX, field.to_ascii_uppercase())
+}
+
+/// A raw value for a field, either an environment string or a JSON value
+enum RawValue<'a> {
+    Env(&'a str),
+    Json(&'a Value),
+}
+
+impl RawValue<'_> {
+    fn as_string(&self) -> Result<String, String> {
+        match self {
+            RawValue::Env(s) => Ok(s.to_string()),
+            RawValue::Json(Value::String(s)) => Ok(s.clone()),
+            RawValue::Json(v) => Err(format!("expected a string, got {}", v)),
+        }
+    }
+
+    fn as_bool(&self) -> Result<bool, String> {
+        match self {
+            RawValue::Env(s) => parse_bool(s),
+            RawValue::Json(Value::Bool(b)) => Ok(*b),
+            RawValue::Json(v) => Err(format!("expected a boolean, got {}", v)),
+        }
+    }
+
+    fn as_u64(&self) -> Result<u64, String> {
+        match self {
+            RawValue::Env(s) => s.trim().parse::<u64>().map_err(|e| e.to_string()),
+            RawValue::Json(v) => v.as_u64().ok_or_else(|| format!("expected a number, got {}", v)),
+        }
+    }
+
+    fn as_i32(&self) -> Result<i32, String> {
+        match self {
+            RawValue::Env(s) => s.trim().parse::<i32>().map_err(|e| e.to_string()),
+            RawValue::Json(v) => v
+                .as_i64()
+                .and_then(|n| i32::try_from(n).ok())
+                .ok_or_else(|| format!("expected a number, got {}", v)),
+        }
+    }
+
+    fn as_list(&self) -> Result<Vec<String>, String> {
+        match self {
+            RawValue::Env(s) => Ok(s
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect()),
+            RawValue::Json(Value::Array(items)) => items
+                .iter()
+                .map(|item| {
+                    item.as_str()
+                        .map(String::from)
+                        .ok_or_else(|| format!("expected a string list item, got {}", item))
+                })
+                .collect(),
+            RawValue::Json(v) => Err(format!("expected a list, got {}", v)),
+        }
+    }
+
+    fn as_object(&self) -> Result<serde_json::Map<String, Value>, String> {
+        let value = match self {
+            RawValue::Env(s) => serde_json::from_str::<Value>(s).map_err(|e| e.to_string())?,
+            RawValue::Json(v) => (*v).clone(),
+        };
+        match value {
+            Value::Object(map) => Ok(map),
+            other => Err(format!("expected a JSON object, got {}", other)),
+        }
+    }
+}
+
+/// Parse a boolean the way the environment layer does
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" | "" => Ok(false),
+        other => Err(format!("expected a boolean, got '{}'", other)),
+    }
+}
+
+/// `{"server": "socks5://proxy:1080", "bypass_list": ["localhost"]}`
+fn parse_proxy_object(object: &serde_json::Map<String, Value>) -> Result<ProxyConfig, String> {
+    let server = object
+        .get("server")
+        .and_then(Value::as_str)
+        .ok_or("expected a \"server\" string in the proxy object")?;
+    let proxy = server.parse::<ProxyConfig>()?;
+    match object.get("bypass_list") {
+        Some(list) => proxy.with_bypass_list(RawValue::Json(list).as_list()?),
+        None => Ok(proxy),
+    }
+}
+
+/// `"com.example.helper=/usr/bin/helper"` or
+/// `{"name": ..., "path": ..., "allowed_origins": [...], "description": ..., "location": "user"}`
+fn parse_native_messaging_host(value: &Value) -> Result<NativeMessagingHost, String> {
+    let object = match value {
+        Value::String(spec) => return spec.parse(),
+        Value::Object(object) => object,
+        other => return Err(format!("expected a native messaging host string or object, got {}", other)),
+    };
+    let field = |name: &str| object.get(name).and_then(Value::as_str);
+    let name = field("name").ok_or("expected a \"name\" string in the native messaging host object")?;
+    let path = field("path").ok_or("expected a \"path\" string in the native messaging host object")?;
+    let mut host = NativeMessagingHost::new(name, path);
+    host.description = field("description").map(String::from);
+    if let Some(origins) = object.get("allowed_origins") {
+        host.allowed_origins = RawValue::Json(origins).as_list()?;
+    }
+    if let Some(location) = field("location") {
+        host.location = location.parse()?;
+    }
+    Ok(host)
+}
+
+/// Generate `FIELDS`, `apply_field`, `is_set` and `Options::or` from one
+/// table of `field => parse`, where `parse` turns `raw` into the field's value
+macro_rules! option_fields {
+    (|$raw:ident| $($field:ident => $parse:expr,)*) => {
+        /// Names of all `Options` fields that can be configured from env or file
+        pub const FIELDS: &[&str] = &[$(stringify!($field)),*];
+
+        fn apply_field(options: &mut Options, field: &str, $raw: RawValue) -> Result<(), String> {
+            match field {
+                $(stringify!($field) => options.$field = Some($parse),)*
+                _ => return Err(format!("Unknown option: {}", field)),
+            }
+            Ok(())
+        }
+
+        /// Whether `field` is set on `options`
+        fn is_set(options: &Options, field: &str) -> bool {
+            match field {
+                $(stringify!($field) => options.$field.is_some(),)*
+                _ => false,
+            }
+        }
+
+        impl Options {
+            /// Fill every unset field from `fallback`; fields already set win
+            pub fn or(self, fallback: Options) -> Options {
+                Options {
+                    $($field: self.$field.or(fallback.$field),)*
+                }
+            }
+        }
+    };
+}
+
+option_fields! { |raw|
+    starting_url => raw.as_string()?,
+    starting_urls => raw.as_list()?,
+    browser_flags => raw.as_list()?,
+    prefs => raw.as_object()?.into_iter().collect(),
+    port => {
+        let port = raw.as_u64()?;
+        u16::try_from(port).map_err(|_| format!("port out of range: {}", port))?
+    },
+    handle_sigint => raw.as_bool()?,
+    browser_path => raw.as_string()?,
+    user_data_dir => raw.as_string()?,
+    log_level => raw.as_string()?,
+    ignore_default_flags => raw.as_bool()?,
+    connection_poll_interval => raw.as_u64()?,
+    max_connection_retries => {
+        let retries = raw.as_u64()?;
+        u32::try_from(retries).map_err(|_| format!("value out of range: {}", retries))?
+    },
+    env_vars => {
+        let mut vars = HashMap::new();
+        for (key, value) in raw.as_object()? {
+            let value = match value {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+            vars.insert(key, value);
+        }
+        vars
+    },
+    browser => raw.as_string()?.parse::<BrowserType>()?,
+    protocol => raw.as_string()?.parse::<Protocol>()?,
+    headless => raw.as_bool()?,
+    window_size => {
+        let size = raw.as_string()?;
+        parse_window_size(&size).ok_or_else(|| format!("expected WIDTHxHEIGHT, got '{}'", size))?
+    },
+    device => raw.as_string()?.parse::<DevicePreset>()?,
+    window_position => {
+        let position = raw.as_string()?;
+        parse_window_position(&position).ok_or_else(|| format!("expected X,Y, got '{}'", position))?
+    },
+    start_maximized => raw.as_bool()?,
+    fullscreen => raw.as_bool()?,
+    kiosk => raw.as_bool()?,
+    app_url => raw.as_string()?,
+    virtual_display => raw.as_bool()?,
+    virtual_display_size => {
+        let size = raw.as_string()?;
+        parse_window_size(&size).ok_or_else(|| format!("expected WIDTHxHEIGHT, got '{}'", size))?
+    },
+    ozone_platform => raw.as_string()?.parse::<OzonePlatform>()?,
+    resource_limits => raw.as_string()?.parse::<ResourceLimits>()?,
+    nice => raw.as_i32()?,
+    cpu_affinity => match raw {
+        RawValue::Json(Value::Array(cpus)) => cpus
+            .iter()
+            .map(|cpu| cpu.as_u64().map(|cpu| cpu as usize).ok_or_else(|| format!("expected a CPU number, got {}", cpu)))
+            .collect::<Result<Vec<usize>, String>>()?,
+        _ => parse_cpu_list(&raw.as_string()?)?,
+    },
+    oom_score_adj => raw.as_i32()?,
+    incognito => raw.as_bool()?,
+    disable_gpu => raw.as_bool()?,
+    no_sandbox => raw.as_bool()?,
+    sandbox_policy => raw.as_string()?.parse::<SandboxPolicy>()?,
+    sandbox_wrapper => match raw {
+        // A list is taken verbatim as a prefix command
+        RawValue::Json(Value::Array(_)) => SandboxWrapper::Prefix(raw.as_list()?),
+        _ => raw.as_string()?.parse::<SandboxWrapper>()?,
+    },
+    wrapper => match raw {
+        RawValue::Json(Value::Array(_)) => raw.as_list()?,
+        _ => raw.as_string()?.split_whitespace().map(String::from).collect(),
+    },
+    disable_web_security => raw.as_bool()?,
+    allow_running_insecure_content => raw.as_bool()?,
+    ignore_ssl_errors => raw.as_bool()?,
+    trusted_certificates => raw.as_list()?.into_iter().map(PathBuf::from).collect(),
+    disable_extensions => raw.as_bool()?,
+    extensions => raw.as_list()?.into_iter().map(PathBuf::from).collect(),
+    native_messaging_hosts => match raw {
+        RawValue::Json(Value::Array(hosts)) => hosts
+            .iter()
+            .map(parse_native_messaging_host)
+            .collect::<Result<_, _>>()?,
+        _ => raw.as_list()?.iter().map(|spec| spec.parse()).collect::<Result<_, _>>()?,
+    },
+    disable_plugins => raw.as_bool()?,
+    disable_images => raw.as_bool()?,
+    disable_javascript => raw.as_bool()?,
+    user_agent => raw.as_string()?,
+    locale => raw.as_string()?,
+    timezone => raw.as_string()?,
+    proxy_server => raw.as_string()?,
+    proxy => {
+        let is_object = match raw {
+            RawValue::Env(s) => s.trim_start().starts_with('{'),
+            RawValue::Json(v) => v.is_object(),
+        };
+        match is_object {
+            true => parse_proxy_object(&raw.as_object()?)?,
+            false => raw.as_string()?.parse::<ProxyConfig>()?,
+        }
+    },
+    host_resolver_rules => raw.as_string()?.parse::<HostResolverRules>()?,
+    additional_args => raw.as_list()?,
+}
+
+impl Options {
+    /// Build options from `BROWSER_LAUNCHER_*` environment variables
+    pub fn from_env() -> Result<Options, String> {
+        Self::from_env_lookup(|name| env::var(name).ok())
+    }
+
+    /// Build options from environment-style variables provided by `lookup`
+    pub fn from_env_lookup<F>(lookup: F) -> Result<Options, String>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let (options, errors, _) = env_layer(lookup);
+        first_error(options, errors)
+    }
+
+    /// Build options from a JSON config file keyed by field name
+    pub fn from_config_file<P: AsRef<Path>>(path: P) -> Result<Options, String> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?;
+        Self::from_config_str(&content)
+            .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+    }
+
+    /// Build options from the contents of a JSON config file
+    pub fn from_config_str(content: &str) -> Result<Options, String> {
+        let (options, errors) = config_layer(content)?;
+        first_error(options, errors)
+    }
+
+    /// Apply the environment and config file layers beneath these options
+    pub fn resolve(self) -> Result<Options, String> {
+        let layers = apply_layers(self);
+        first_error(layers.options, layers.errors)
+    }
+}
+
+/// A layer value that could not be used, and the field it was meant for
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerError {
+    /// `None` when no single field is affected, e.g. an unreadable config file
+    pub field: Option<&'static str>,
+    pub message: String,
+}
+
+impl LayerError {
+    fn new(field: Option<&'static str>, message: String) -> Self {
+        LayerError { field, message }
+    }
+}
+
+/// `field` as one of the `FIELDS` entries, if it is one
+fn known_field(field: &str) -> Option<&'static str> {
+    FIELDS.iter().find(|known| **known == field).copied()
+}
+
+/// Options from environment-style variables, leaving out invalid ones.
+/// Returns their errors, and a warning for an unusable legacy `HEADLESS`.
+fn env_layer<F>(lookup: F) -> (Options, Vec<LayerError>, Vec<LayerError>)
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut options = Options::default();
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    for field in FIELDS {
+        let name = env_var_name(field);
+        if let Some(value) = lookup(&name) {
+            if let Err(e) = apply_field(&mut options, field, RawValue::Env(&value)) {
+                errors.push(LayerError::new(Some(field), format!("Invalid value for {}: {}", name, e)));
+            }
+        }
+    }
+
+    // The legacy variable is often set for other tools (e.g. HEADLESS=new),
+    // so a value we cannot read is ignored rather than treated as an error
+    if options.headless.is_none() && lookup(&env_var_name("headless")).is_none() {
+        if let Some(value) = lookup(LEGACY_HEADLESS_ENV_VAR) {
+            match parse_bool(&value) {
+                Ok(headless) => options.headless = Some(headless),
+                Err(e) => warnings.push(LayerError::new(
+                    Some("headless"),
+                    format!("Ignoring {}: {}", LEGACY_HEADLESS_ENV_VAR, e),
+                )),
+            }
+        }
+    }
+
+    (options, errors, warnings)
+}
+
+/// Options from a JSON config file, leaving out invalid keys; returns their errors
+fn config_layer(content: &str) -> Result<(Options, Vec<LayerError>), String> {
+    let value: Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    let object = value
+        .as_object()
+        .ok_or_else(|| "expected a JSON object at the top level".to_string())?;
+
+    let mut options = Options::default();
+    let mut errors = Vec::new();
+    for (field, value) in object {
+        if let Err(e) = apply_field(&mut options, field, RawValue::Json(value)) {
+            errors.push(LayerError::new(known_field(field), format!("{}: {}", field, e)));
+        }
+    }
+    Ok((options, errors))
+}
+
+fn first_error(options: Options, errors: Vec<LayerError>) -> Result<Options, String> {
+    match errors.into_iter().next() {
+        Some(e) => Err(e.message),
+        None => Ok(options),
+    }
+}
+
+/// Drop the problems of fields that `options` sets, since a higher layer overrides them
+fn drop_overridden(problems: &mut Vec<LayerError>, options: &Options) {
+    problems.retain(|problem| !problem.field.is_some_and(|field| is_set(options, field)));
+}
+
+/// Options from the environment and config file, with the origin of each set field
+#[derive(Debug, Default)]
+pub struct Layers {
+    pub options: Options,
+    pub sources: HashMap<&'static str, FlagSource>,
+    /// Variables, config keys or a config file that could not be used; each is left out.
+    /// Problems with a field that a higher layer sets are not reported.
+    pub errors: Vec<LayerError>,
+    /// Values that were ignored without failing the launch
+    pub warnings: Vec<LayerError>,
+}
+
+/// Load the environment layer on top of the config file layer
+pub fn load() -> Layers {
+    let (env_options, mut errors, warnings) = env_layer(|name| env::var(name).ok());
+    let config = match env::var(CONFIG_ENV_VAR) {
+        Ok(path) => {
+            let layer = fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read config file {}: {}", path, e))
+                .and_then(|content| {
+                    config_layer(&content).map_err(|e| format!("Invalid config file {}: {}", path, e))
+                });
+            match layer {
+                Ok((options, mut file_errors)) => {
+                    drop_overridden(&mut file_errors, &env_options);
+                    errors.extend(file_errors.into_iter().map(|e| LayerError {
+                        message: format!("Invalid config file {}: {}", path, e.message),
+                        ..e
+                    }));
+                    Some((options, path))
+                }
+                Err(e) => {
+                    errors.push(LayerError::new(None, e));
+                    None
+                }
+            }
+        }
+        Err(_) => None,
+    };
+
+    let mut sources = HashMap::new();
+    for field in FIELDS {
+        if is_set(&env_options, field) {
+            let name = env_var_name(field);
+            let name = if *field == "headless" && env::var(&name).is_err() {
+                LEGACY_HEADLESS_ENV_VAR.to_string()
+            } else {
+                name
+            };
+            sources.insert(*field, FlagSource::Env(name));
+        } else if let Some((ref file_options, ref path)) = config {
+            if is_set(file_options, field) {
+                sources.insert(*field, FlagSource::ConfigFile(path.clone()));
+            }
+        }
+    }
+
+    let options = match config {
+        Some((file_options, _)) => env_options.or(file_options),
+        None => env_options,
+    };
+    Layers {
+        options,
+        sources,
+        errors,
+        warnings,
+    }
+}
+
+/// Layer the environment and config file beneath `explicit` and report where
+/// every set field came from. Invalid layers are reported and skipped.
+pub fn apply_layers(explicit: Options) -> Layers {
+    let mut layers = load();
+
+    for field in FIELDS {
+        if is_set(&explicit, field) {
+            layers.sources.insert(*field, FlagSource::Option(field));
+        }
+    }
+    drop_overridden(&mut layers.errors, &explicit);
+    drop_overridden(&mut layers.warnings, &explicit);
+    layers.options = explicit.or(layers.options);
+    layers
+}
@@ -0,0 +1,134 @@
+//! Bundled device presets for mobile and tablet layouts
+
+use std::fmt;
+use std::str::FromStr;
+
+/// User agent, window size, device scale factor and touch support of a device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DevicePreset {
+    /// Name used by `--device`, e.g. `pixel-7`
+    pub id: &'static str,
+    /// Display name, e.g. `Pixel 7`
+    pub name: &'static str,
+    pub user_agent: &'static str,
+    /// CSS pixel size of the screen in portrait orientation
+    pub window_size: (u32, u32),
+    pub device_scale_factor: f64,
+    pub touch: bool,
+}
+
+/// Every bundled preset
+pub const DEVICE_PRESETS: &[DevicePreset] = &[
+    DevicePreset {
+        id: "pixel-7",
+        name: "Pixel 7",
+        user_agent: "Mozilla/5.0 (Linux; Android 13; Pixel 7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Mobile Safari/537.36",
+        window_size: (412, 915),
+        device_scale_factor: 2.625,
+        touch: true,
+    },
+    DevicePreset {
+        id: "pixel-5",
+        name: "Pixel 5",
+        user_agent: "Mozilla/5.0 (Linux; Android 11; Pixel 5) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Mobile Safari/537.36",
+        window_size: (393, 851),
+        device_scale_factor: 2.75,
+        touch: true,
+    },
+    DevicePreset {
+        id: "galaxy-s20",
+        name: "Galaxy S20",
+        user_agent: "Mozilla/5.0 (Linux; Android 13; SM-G981B) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Mobile Safari/537.36",
+        window_size: (360, 800),
+        device_scale_factor: 3.0,
+        touch: true,
+    },
+    DevicePreset {
+        id: "iphone-se",
+        name: "iPhone SE",
+        user_agent: "Mozilla/5.0 (iPhone; CPU iPhone OS 16_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.0 Mobile/15E148 Safari/604.1",
+        window_size: (375, 667),
+        device_scale_factor: 2.0,
+        touch: true,
+    },
+    DevicePreset {
+        id: "iphone-14",
+        name: "iPhone 14",
+        user_agent: "Mozilla/5.0 (iPhone; CPU iPhone OS 16_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.0 Mobile/15E148 Safari/604.1",
+        window_size: (390, 844),
+        device_scale_factor: 3.0,
+        touch: true,
+    },
+    DevicePreset {
+        id: "iphone-14-pro-max",
+        name: "iPhone 14 Pro Max",
+        user_agent: "Mozilla/5.0 (iPhone; CPU iPhone OS 16_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.0 Mobile/15E148 Safari/604.1",
+        window_size: (430, 932),
+        device_scale_factor: 3.0,
+        touch: true,
+    },
+    DevicePreset {
+        id: "ipad",
+        name: "iPad",
+        user_agent: "Mozilla/5.0 (iPad; CPU OS 16_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.0 Mobile/15E148 Safari/604.1",
+        window_size: (810, 1080),
+        device_scale_factor: 2.0,
+        touch: true,
+    },
+    DevicePreset {
+        id: "ipad-pro-11",
+        name: "iPad Pro 11",
+        user_agent: "Mozilla/5.0 (iPad; CPU OS 16_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.0 Mobile/15E148 Safari/604.1",
+        window_size: (834, 1194),
+        device_scale_factor: 2.0,
+        touch: true,
+    },
+    DevicePreset {
+        id: "galaxy-tab-s8",
+        name: "Galaxy Tab S8",
+        user_agent: "Mozilla/5.0 (Linux; Android 13; SM-X700) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
+        window_size: (800, 1280),
+        device_scale_factor: 2.0,
+        touch: true,
+    },
+];
+
+impl DevicePreset {
+    /// Look up a preset by id or display name, ignoring case, spaces and underscores
+    pub fn find(name: &str) -> Option<&'static DevicePreset> {
+        let wanted = normalize(name);
+        DEVICE_PRESETS
+            .iter()
+            .find(|preset| preset.id == wanted || normalize(preset.name) == wanted)
+    }
+
+    /// Chromium switches for the scale factor and touch events
+    pub fn chromium_flags(&self) -> Vec<String> {
+        let mut flags = vec![format!("--force-device-scale-factor={}", self.device_scale_factor)];
+        if self.touch {
+            flags.push("--touch-events=enabled".to_string());
+        }
+        flags
+    }
+}
+
+impl fmt::Display for DevicePreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
+impl FromStr for DevicePreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DevicePreset::find(s).copied().ok_or_else(|| {
+            let ids: Vec<&str> = DEVICE_PRESETS.iter().map(|preset| preset.id).collect();
+            format!("Unknown device '{}' (expected one of: {})", s, ids.join(", "))
+        })
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase().replace([' ', '_'], "-")
+}
@@ -0,0 +1,130 @@
+//! Minimal DevTools HTTP endpoint helpers (`/json/version`, `/json/list`)
+
+use serde_json::Value;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::Duration;
+
+/// File Chromium writes into the user data dir once DevTools is listening
+pub const ACTIVE_PORT_FILE: &str = "DevToolsActivePort";
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An open page, worker or other DevTools target
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub id: String,
+    pub target_type: String,
+    pub title: String,
+    pub url: String,
+    pub web_socket_debugger_url: Option<String>,
+}
+
+impl Target {
+    fn from_json(value: &Value) -> Self {
+        let field = |name: &str| value[name].as_str().unwrap_or_default().to_string();
+        Self {
+            id: field("id"),
+            target_type: field("type"),
+            title: field("title"),
+            url: field("url"),
+            web_socket_debugger_url: value["webSocketDebuggerUrl"].as_str().map(String::from),
+        }
+    }
+}
+
+/// Read the port and browser websocket path from `DevToolsActivePort`
+pub fn read_active_port(user_data_dir: &Path) -> Option<(u16, String)> {
+    let content = fs::read_to_string(user_data_dir.join(ACTIVE_PORT_FILE)).ok()?;
+    let mut lines = content.lines();
+    let port = lines.next()?.trim().parse::<u16>().ok()?;
+    let path = lines.next().unwrap_or_default().trim().to_string();
+    Some((port, path))
+}
+
+/// Find the WebDriver BiDi websocket URL Firefox prints to stderr on startup
+pub fn read_bidi_listening_url(log_file: &Path) -> Option<String> {
+    let log = fs::read_to_string(log_file).ok()?;
+    log.lines().find_map(|line| {
+        let (_, url) = line.split_once("WebDriver BiDi listening on ")?;
+        Some(url.trim().to_string())
+    })
+}
+
+/// Extract the port from a `ws://host:port/...` URL
+pub fn port_from_ws_url(url: &str) -> Option<u16> {
+    let authority = url.split("://").nth(1)?.split('/').next()?;
+    authority.rsplit_once(':')?.1.parse().ok()
+}
+
+/// Perform a plain HTTP/1.1 request against 127.0.0.1 and return the body
+pub fn http_request(port: u16, method: &str, path: &str) -> Result<String, String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT)).map_err(|e| e.to_string())?;
+
+    // DevTools rejects Host headers that are not an IP or localhost
+    let request = format!(
+        "{} {} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        method, path, port
+    );
+    stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).map_err(|e| e.to_string())?;
+    let response = String::from_utf8_lossy(&response);
+
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| "Malformed HTTP response".to_string())?;
+    let status = head
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| "Malformed HTTP status line".to_string())?;
+    if !(200..300).contains(&status) {
+        return Err(format!("{} {} returned HTTP {}: {}", method, path, status, body.trim()));
+    }
+    Ok(body.to_string())
+}
+
+/// GET a DevTools JSON endpoint
+pub fn get_json(port: u16, path: &str) -> Result<Value, String> {
+    let body = http_request(port, "GET", path)?;
+    serde_json::from_str(&body).map_err(|e| format!("Invalid JSON from {}: {}", path, e))
+}
+
+/// Query `/json/version`
+pub fn version(port: u16) -> Result<Value, String> {
+    get_json(port, "/json/version")
+}
+
+/// Open `url` in a new tab with `PUT /json/new`
+pub fn open_tab(port: u16, url: &str) -> Result<Target, String> {
+    let body = http_request(port, "PUT", &format!("/json/new?{}", encode_query(url)))?;
+    let value: Value = serde_json::from_str(&body).map_err(|e| format!("Invalid JSON from /json/new: {}", e))?;
+    Ok(Target::from_json(&value))
+}
+
+/// Percent-encode everything but unreserved characters
+fn encode_query(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Query `/json/list` for the open targets
+pub fn list_targets(port: u16) -> Result<Vec<Target>, String> {
+    let value = get_json(port, "/json/list")?;
+    let targets = value
+        .as_array()
+        .ok_or_else(|| "Expected a JSON array from /json/list".to_string())?;
+    Ok(targets.iter().map(Target::from_json).collect())
+}
@@ -0,0 +1,176 @@
+//! Display handling: Xvfb virtual displays and Ozone platform selection
+
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+/// Screen size used when neither `virtual_display_size` nor `window_size` is set
+pub const DEFAULT_SCREEN_SIZE: (u32, u32) = (1920, 1080);
+
+/// First display number tried; low numbers are usually taken by real sessions
+const FIRST_DISPLAY: u32 = 99;
+const DISPLAY_ATTEMPTS: u32 = 50;
+const STARTUP_POLLS: u32 = 100;
+const STARTUP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A running Xvfb server; stopped when dropped
+#[derive(Debug)]
+pub struct VirtualDisplay {
+    /// Display number, as in `DISPLAY=:99`
+    pub number: u32,
+    pub size: (u32, u32),
+    process: Option<Child>,
+}
+
+impl VirtualDisplay {
+    /// Start Xvfb on the first free display number with a `width`x`height`x24 screen
+    pub fn start(size: (u32, u32)) -> Result<Self, String> {
+        if !cfg!(unix) || cfg!(target_os = "macos") {
+            return Err("virtual_display requires an X11 host with Xvfb".to_string());
+        }
+        let xvfb = find_xvfb().ok_or(
+            "virtual_display is enabled but Xvfb was not found on PATH; install Xvfb (e.g. the xvfb package) or disable virtual_display",
+        )?;
+
+        let mut number = FIRST_DISPLAY;
+        for _ in 0..DISPLAY_ATTEMPTS {
+            number = next_free_display(number);
+            let mut process = Command::new(&xvfb)
+                .arg(format!(":{}", number))
+                .args(["-screen", "0", &format!("{}x{}x24", size.0, size.1)])
+                .args(["-nolisten", "tcp"])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .map_err(|e| format!("Failed to start {}: {}", xvfb.display(), e))?;
+
+            // Xvfb exits at once if another server grabbed the display in the meantime
+            for _ in 0..STARTUP_POLLS {
+                if let Ok(Some(_)) = process.try_wait() {
+                    break;
+                }
+                if socket_path(number).exists() {
+                    return Ok(Self {
+                        number,
+                        size,
+                        process: Some(process),
+                    });
+                }
+                thread::sleep(STARTUP_POLL_INTERVAL);
+            }
+            let _ = process.kill();
+            let _ = process.wait();
+            number += 1;
+        }
+        Err(format!("Could not start Xvfb on any display from :{} to :{}", FIRST_DISPLAY, number))
+    }
+
+    /// Value for the `DISPLAY` environment variable
+    pub fn name(&self) -> String {
+        format!(":{}", self.number)
+    }
+
+    /// Stop the Xvfb server
+    pub fn stop(&mut self) {
+        if let Some(mut process) = self.process.take() {
+            let _ = process.kill();
+            let _ = process.wait();
+        }
+    }
+}
+
+impl Drop for VirtualDisplay {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn find_xvfb() -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join("Xvfb"))
+        .find(|path| path.is_file())
+}
+
+fn socket_path(number: u32) -> PathBuf {
+    PathBuf::from(format!("/tmp/.X11-unix/X{}", number))
+}
+
+/// The first display number from `start` without a lock file or socket
+fn next_free_display(start: u32) -> u32 {
+    (start..)
+        .find(|number| !Path::new(&format!("/tmp/.X{}-lock", number)).exists() && !socket_path(*number).exists())
+        .unwrap_or(start)
+}
+
+/// Chromium Ozone platform backend on Linux
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OzonePlatform {
+    /// Let Chromium pick (`--ozone-platform-hint=auto`)
+    Auto,
+    Wayland,
+    X11,
+}
+
+impl OzonePlatform {
+    /// The command line switch selecting this platform
+    pub fn flag(&self) -> &'static str {
+        match self {
+            OzonePlatform::Auto => "--ozone-platform-hint=auto",
+            OzonePlatform::Wayland => "--ozone-platform=wayland",
+            OzonePlatform::X11 => "--ozone-platform=x11",
+        }
+    }
+}
+
+impl fmt::Display for OzonePlatform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OzonePlatform::Auto => write!(f, "auto"),
+            OzonePlatform::Wayland => write!(f, "wayland"),
+            OzonePlatform::X11 => write!(f, "x11"),
+        }
+    }
+}
+
+impl FromStr for OzonePlatform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(OzonePlatform::Auto),
+            "wayland" => Ok(OzonePlatform::Wayland),
+            "x11" => Ok(OzonePlatform::X11),
+            other => Err(format!("Unknown ozone platform: {}", other)),
+        }
+    }
+}
+
+/// Work out the Ozone platform from the session variables returned by `lookup`,
+/// together with the variable that decided it.
+///
+/// A Wayland socket wins; a Wayland session without one is left to Chromium,
+/// and `DISPLAY` or an X11 session means X11.
+pub fn detect_ozone_platform<F>(lookup: F) -> Option<(OzonePlatform, &'static str)>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let is_set = |name: &str| lookup(name).is_some_and(|value| !value.is_empty());
+    let session_type = lookup("XDG_SESSION_TYPE").unwrap_or_default().to_ascii_lowercase();
+
+    if is_set("WAYLAND_DISPLAY") {
+        Some((OzonePlatform::Wayland, "WAYLAND_DISPLAY"))
+    } else if session_type == "wayland" {
+        Some((OzonePlatform::Auto, "XDG_SESSION_TYPE"))
+    } else if is_set("DISPLAY") {
+        Some((OzonePlatform::X11, "DISPLAY"))
+    } else if session_type == "x11" {
+        Some((OzonePlatform::X11, "XDG_SESSION_TYPE"))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,248 @@
+//! chromedriver discovery and launching
+
+use crate::browser::{Browser, BrowserFinder, BrowserType};
+use crate::devtools;
+use crate::utils::{free_port, get_default};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// Environment variable pointing at a specific driver executable
+pub const DRIVER_PATH_ENV_VAR: &str = "CHROMEDRIVER_PATH";
+
+/// A WebDriver executable such as chromedriver
+#[derive(Debug, Clone)]
+pub struct Driver {
+    pub executable_path: String,
+    pub version: Option<String>,
+}
+
+impl Driver {
+    /// Create a new Driver instance
+    pub fn new(executable_path: String) -> Self {
+        Self {
+            executable_path,
+            version: None,
+        }
+    }
+
+    /// Try to get the driver version
+    pub fn get_version(&mut self) -> Option<String> {
+        if self.version.is_none() {
+            // e.g. "ChromeDriver 120.0.6099.109 (3419140ab665596f21b385ce136419fde0924272-refs/...)"
+            let output = Command::new(&self.executable_path).arg("--version").output().ok()?;
+            self.version = String::from_utf8_lossy(&output.stdout)
+                .split_whitespace()
+                .find(|part| part.starts_with(|c: char| c.is_ascii_digit()))
+                .map(String::from);
+        }
+        self.version.clone()
+    }
+}
+
+/// The major component of a dotted version string
+pub fn major_version(version: &str) -> Option<u32> {
+    version.split('.').next()?.parse().ok()
+}
+
+/// Driver executables worth trying for `browser`, in order: `CHROMEDRIVER_PATH`,
+/// next to the browser executable (following symlinks), then `PATH`
+pub fn driver_candidates(browser: &Browser) -> Vec<PathBuf> {
+    let names: Vec<String> = browser
+        .browser_type
+        .driver_executables()
+        .iter()
+        .map(|name| format!("{}{}", name, env::consts::EXE_SUFFIX))
+        .collect();
+
+    let mut dirs = Vec::new();
+    let executable = Path::new(&browser.executable_path);
+    dirs.extend(executable.parent().map(Path::to_path_buf));
+    if let Ok(real) = executable.canonicalize() {
+        dirs.extend(real.parent().map(Path::to_path_buf));
+    }
+    if let Some(path) = env::var_os("PATH") {
+        dirs.extend(env::split_paths(&path));
+    }
+
+    let mut candidates: Vec<PathBuf> = env::var_os(DRIVER_PATH_ENV_VAR).map(PathBuf::from).into_iter().collect();
+    for dir in dirs {
+        for name in &names {
+            let candidate = dir.join(name);
+            if candidate.is_file() && !candidates.contains(&candidate) {
+                candidates.push(candidate);
+            }
+        }
+    }
+    candidates
+}
+
+/// Find a driver whose major version matches `browser`.
+///
+/// When the browser version cannot be determined the first driver found is used.
+pub fn find_driver(browser: &mut Browser) -> Result<Driver, String> {
+    let candidates = driver_candidates(browser);
+    if candidates.is_empty() {
+        return Err(format!("No WebDriver executable found for {}", browser.name()));
+    }
+
+    let browser_major = match browser.get_version().as_deref().and_then(major_version) {
+        Some(major) => major,
+        None => return Ok(Driver::new(candidates[0].to_string_lossy().to_string())),
+    };
+
+    let mut found = Vec::new();
+    for candidate in candidates {
+        let mut driver = Driver::new(candidate.to_string_lossy().to_string());
+        let version = driver.get_version();
+        if version.as_deref().and_then(major_version) == Some(browser_major) {
+            return Ok(driver);
+        }
+        found.push(format!(
+            "{} ({})",
+            driver.executable_path,
+            version.unwrap_or_else(|| "unknown version".to_string())
+        ));
+    }
+    Err(format!(
+        "No WebDriver matches {} {}; found {}",
+        browser.name(),
+        browser_major,
+        found.join(", ")
+    ))
+}
+
+#[derive(Default, Debug)]
+pub struct DriverOptions {
+    /// Driver executable; discovered from the browser when unset
+    pub driver_path: Option<String>,
+    pub browser: Option<BrowserType>,
+    pub browser_path: Option<String>,
+    /// Port to listen on; a free one is picked when unset
+    pub port: Option<u16>,
+    /// File the driver writes its log to (`--log-path`)
+    pub log_file: Option<String>,
+    pub args: Option<Vec<String>>,
+    pub connection_poll_interval: Option<u64>,
+    pub max_connection_retries: Option<u32>,
+}
+
+pub struct LaunchedDriver {
+    pub pid: u32,
+    pub port: u16,
+    pub process: std::process::Child,
+    /// Base URL of the WebDriver HTTP endpoint
+    pub url: String,
+    pub driver: Driver,
+}
+
+impl LaunchedDriver {
+    /// Wait for the driver to exit
+    pub fn wait(&mut self) -> std::io::Result<std::process::ExitStatus> {
+        self.process.wait()
+    }
+
+    /// Kill the driver and reap it, which releases its port
+    pub fn kill(&mut self) -> std::io::Result<()> {
+        let killed = self.process.kill();
+        let _ = self.process.wait();
+        killed
+    }
+}
+
+pub struct DriverLauncher {
+    driver_path: Option<String>,
+    browser_type: BrowserType,
+    browser_path: Option<String>,
+    port: Option<u16>,
+    log_file: Option<String>,
+    args: Vec<String>,
+    connection_poll_interval: u64,
+    max_connection_retries: u32,
+}
+
+impl DriverLauncher {
+    pub fn new(opts: DriverOptions) -> Self {
+        Self {
+            driver_path: opts.driver_path,
+            browser_type: get_default(opts.browser, || BrowserType::Chrome),
+            browser_path: opts.browser_path,
+            port: opts.port,
+            log_file: opts.log_file,
+            args: get_default(opts.args, Vec::new),
+            connection_poll_interval: get_default(opts.connection_poll_interval, || 500),
+            max_connection_retries: get_default(opts.max_connection_retries, || 50),
+        }
+    }
+
+    /// Resolve the driver executable that `launch` would run
+    pub fn driver(&self) -> Result<Driver, String> {
+        if let Some(ref path) = self.driver_path {
+            return Ok(Driver::new(path.clone()));
+        }
+        let mut browser = match self.browser_path {
+            Some(ref path) => Browser::new(self.browser_type.clone(), path.clone()),
+            None => BrowserFinder::new(vec![self.browser_type.clone()])
+                .find_first()
+                .ok_or_else(|| format!("{} browser not found on this system", self.browser_type.name()))?,
+        };
+        find_driver(&mut browser)
+    }
+
+    /// Start the driver and wait until `/status` reports it ready
+    pub fn launch(&self) -> Result<LaunchedDriver, String> {
+        let driver = self.driver()?;
+        let port = match self.port {
+            Some(port) => port,
+            None => free_port()?,
+        };
+
+        let mut command = Command::new(&driver.executable_path);
+        command.arg(format!("--port={}", port));
+        if let Some(ref log_file) = self.log_file {
+            command.arg(format!("--log-path={}", log_file));
+        }
+        command.args(&self.args);
+        // The driver writes its log through --log-path; sending stderr to the
+        // same file would interleave two writers at different offsets
+        command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+
+        let mut process = command
+            .spawn()
+            .map_err(|e| format!("Failed to start {}: {}", driver.executable_path, e))?;
+        if let Err(e) = self.wait_for_status(&mut process, port) {
+            let _ = process.kill();
+            let _ = process.wait();
+            return Err(e);
+        }
+
+        Ok(LaunchedDriver {
+            pid: process.id(),
+            port,
+            process,
+            url: format!("http://127.0.0.1:{}", port),
+            driver,
+        })
+    }
+
+    fn wait_for_status(&self, process: &mut std::process::Child, port: u16) -> Result<(), String> {
+        for _ in 0..self.max_connection_retries.max(1) {
+            if let Ok(Some(status)) = process.try_wait() {
+                return Err(format!("Driver exited ({}) before it was ready", status));
+            }
+            let ready = devtools::get_json(port, "/status")
+                .map(|status| status["value"]["ready"].as_bool().unwrap_or(false))
+                .unwrap_or(false);
+            if ready {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(self.connection_poll_interval));
+        }
+        Err(format!(
+            "Timed out waiting for the driver /status after {} attempts",
+            self.max_connection_retries
+        ))
+    }
+}
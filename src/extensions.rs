@@ -0,0 +1,77 @@
+//! Unpacked Chromium extensions and their IDs
+
+use crate::utils::{base64_decode, sha256};
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// An unpacked extension directory and the ID Chromium gives it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extension {
+    /// Absolute path of the extension directory
+    pub path: PathBuf,
+    /// 32 characters `a`-`p`, from the manifest `key` or else the path
+    pub id: String,
+}
+
+impl Extension {
+    /// Check that `dir` holds a `manifest.json` and work out the extension ID
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let manifest_path = dir.join(MANIFEST_FILE);
+        let manifest = fs::read_to_string(&manifest_path)
+            .map_err(|e| format!("Extension {} has no readable {}: {}", dir.display(), MANIFEST_FILE, e))?;
+        let manifest: Value = serde_json::from_str(&manifest)
+            .map_err(|e| format!("Invalid {}: {}", manifest_path.display(), e))?;
+        // Chromium resolves symlinks before hashing the path
+        let path = fs::canonicalize(dir).map_err(|e| format!("Failed to resolve {}: {}", dir.display(), e))?;
+
+        let id = match manifest.get("key") {
+            Some(Value::String(key)) => {
+                let key: String = key.chars().filter(|c| !c.is_whitespace()).collect();
+                let key = base64_decode(&key)
+                    .ok_or_else(|| format!("Invalid base64 in the key of {}", manifest_path.display()))?;
+                extension_id(&key)
+            }
+            Some(_) => return Err(format!("The key in {} must be a string", manifest_path.display())),
+            None => extension_id(&path_bytes(&path)),
+        };
+        Ok(Self { path, id })
+    }
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.id, self.path.display())
+    }
+}
+
+/// Chromium's extension ID for a public key or path: the first 128 bits of
+/// its SHA-256, written in hex with the digits mapped to `a`-`p`
+pub fn extension_id(input: &[u8]) -> String {
+    sha256(input)[..16]
+        .iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .map(|nibble| (b'a' + nibble) as char)
+        .collect()
+}
+
+/// The bytes Chromium hashes for a path: the raw path on Unix, UTF-16 on Windows
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(windows)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::windows::ffi::OsStrExt;
+    // Chromium upper-cases the drive letter before hashing
+    let mut path = path.to_string_lossy().trim_start_matches(r"\\?\").to_string();
+    if path.chars().nth(1) == Some(':') {
+        path.replace_range(..1, &path[..1].to_ascii_uppercase());
+    }
+    std::ffi::OsStr::new(&path).encode_wide().flat_map(u16::to_le_bytes).collect()
+}
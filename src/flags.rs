@@ -45,3 +45,22 @@ pub struct LauncherError {
     pub message: String,
     pub code: Option<String>,
 }
+
+// Default Firefox preferences written to user.js (values are JSON literals)
+pub const DEFAULT_FIREFOX_PREFS: &[(&str, &str)] = &[
+    // Do not ask to become the default browser
+    ("browser.shell.checkDefaultBrowser", "false"),
+    // Skip the welcome and "what's new" pages
+    ("browser.aboutwelcome.enabled", "false"),
+    ("browser.startup.homepage_override.mstone", "\"ignore\""),
+    // Do not restore previous sessions after a crash
+    ("browser.sessionstore.resume_from_crash", "false"),
+    // Disable telemetry and its first-run notice
+    ("datareporting.policy.dataSubmissionEnabled", "false"),
+    ("toolkit.telemetry.reportingpolicy.firstRun", "false"),
+    // Disable background updates of the browser and add-ons
+    ("app.update.auto", "false"),
+    ("extensions.update.enabled", "false"),
+    // Let the Remote Agent apply its recommended automation preferences
+    ("remote.prefs.recommended", "true"),
+];
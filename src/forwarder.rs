@@ -0,0 +1,198 @@
+//! Local HTTP proxy that forwards to an upstream proxy with credentials.
+//!
+//! Chromium ignores `user:password@` in `--proxy-server`, so the browser is
+//! pointed at this forwarder instead, which adds `Proxy-Authorization` to every
+//! request before passing it upstream.
+//!
+//! On Linux only processes of the current user may connect; elsewhere any
+//! local process can use the forwarder, and with it the credentials.
+
+use crate::proxy::ProxyServer;
+use crate::utils::base64_encode;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Largest request head accepted from the browser
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+/// Connections served at once; further ones are closed right away
+const MAX_CONNECTIONS: usize = 256;
+
+/// A running forwarder on `127.0.0.1`; stopped when dropped
+#[derive(Debug)]
+pub struct ProxyForwarder {
+    pub port: u16,
+    pub upstream: ProxyServer,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ProxyForwarder {
+    /// Listen on `127.0.0.1:port` (0 picks a free port) and forward every
+    /// request to `upstream` with its credentials
+    pub fn start(port: u16, upstream: ProxyServer) -> Result<Self, String> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .map_err(|e| format!("Failed to start the proxy forwarder on 127.0.0.1:{}: {}", port, e))?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+
+        let authorization = upstream.credentials.as_ref().map(|credentials| {
+            let pair = format!("{}:{}", credentials.username, credentials.password);
+            format!("Basic {}", base64_encode(pair.as_bytes()))
+        });
+        let address = upstream.address();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let stop = stop.clone();
+            let connections = Arc::new(AtomicUsize::new(0));
+            move || {
+                for client in listener.incoming() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let Ok(client) = client else {
+                        continue;
+                    };
+                    if !peer_is_current_user(&client, port) || connections.load(Ordering::Relaxed) >= MAX_CONNECTIONS {
+                        continue;
+                    }
+                    connections.fetch_add(1, Ordering::Relaxed);
+                    let connections = connections.clone();
+                    let address = address.clone();
+                    let authorization = authorization.clone();
+                    thread::spawn(move || {
+                        forward(client, &address, authorization.as_deref());
+                        connections.fetch_sub(1, Ordering::Relaxed);
+                    });
+                }
+            }
+        });
+
+        Ok(Self {
+            port,
+            upstream,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Stop accepting connections; open tunnels close with the browser
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            // Wake the blocking accept so the thread sees the flag
+            let _ = TcpStream::connect(("127.0.0.1", self.port));
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for ProxyForwarder {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Whether the process on the other end of `client` runs as the current user,
+/// found from the socket owners in `/proc/net/tcp`
+#[cfg(target_os = "linux")]
+fn peer_is_current_user(client: &TcpStream, listener_port: u16) -> bool {
+    let Ok(std::net::SocketAddr::V4(peer)) = client.peer_addr() else {
+        return false;
+    };
+    let uid = unsafe { libc::getuid() };
+    let port_of = |address: &str| address.rsplit_once(':').and_then(|(_, port)| u16::from_str_radix(port, 16).ok());
+    std::fs::read_to_string("/proc/net/tcp")
+        .unwrap_or_default()
+        .lines()
+        .skip(1)
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        // The peer's socket: local port is the peer port, remote is our listener
+        .find(|fields| {
+            fields.len() > 7 && port_of(fields[1]) == Some(peer.port()) && port_of(fields[2]) == Some(listener_port)
+        })
+        .and_then(|fields| fields[7].parse::<u32>().ok())
+        == Some(uid)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peer_is_current_user(_client: &TcpStream, _listener_port: u16) -> bool {
+    true
+}
+
+/// Pass one browser connection to the upstream proxy
+fn forward(mut client: TcpStream, upstream: &(String, u16), authorization: Option<&str>) {
+    let Ok((head, body)) = read_head(&mut client) else {
+        return;
+    };
+    let mut server = match TcpStream::connect((upstream.0.as_str(), upstream.1)) {
+        Ok(server) => server,
+        Err(_) => {
+            let _ = client.write_all(b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            return;
+        }
+    };
+    if server.write_all(rewrite_head(&head, authorization).as_bytes()).is_err() || server.write_all(&body).is_err() {
+        return;
+    }
+
+    let (Ok(mut server_reader), Ok(mut client_writer)) = (server.try_clone(), client.try_clone()) else {
+        return;
+    };
+    let downstream = thread::spawn(move || {
+        let _ = io::copy(&mut server_reader, &mut client_writer);
+        let _ = client_writer.shutdown(Shutdown::Write);
+    });
+    let _ = io::copy(&mut client, &mut server);
+    let _ = server.shutdown(Shutdown::Write);
+    let _ = downstream.join();
+}
+
+/// Read up to the end of the request head; returns the head and any bytes after it
+fn read_head(client: &mut TcpStream) -> io::Result<(String, Vec<u8>)> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let read = client.read(&mut chunk)?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            let body = buffer.split_off(end + 4);
+            return Ok((String::from_utf8_lossy(&buffer).to_string(), body));
+        }
+        if buffer.len() > MAX_HEAD_SIZE {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+    }
+}
+
+/// Replace any `Proxy-Authorization` in `head` with `authorization`.
+///
+/// Plain (non-CONNECT) requests are limited to one per connection, so that
+/// every request reaches the upstream with credentials.
+pub fn rewrite_head(head: &str, authorization: Option<&str>) -> String {
+    let mut lines = head.split("\r\n").filter(|line| !line.is_empty());
+    let request_line = lines.next().unwrap_or_default();
+    let is_connect = request_line.starts_with("CONNECT ");
+
+    let mut rewritten = format!("{}\r\n", request_line);
+    for line in lines {
+        let name = line.split(':').next().unwrap_or_default().trim().to_ascii_lowercase();
+        let dropped = name == "proxy-authorization" || (!is_connect && (name == "connection" || name == "proxy-connection"));
+        if !dropped {
+            rewritten.push_str(line);
+            rewritten.push_str("\r\n");
+        }
+    }
+    if let Some(authorization) = authorization {
+        rewritten.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+    }
+    if !is_connect {
+        rewritten.push_str("Connection: close\r\n");
+    }
+    rewritten.push_str("\r\n");
+    rewritten
+}
@@ -0,0 +1,134 @@
+//! Detection of root, container and kernel conditions that break Chromium's defaults
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// `/dev/shm` below this size makes Chromium crash on large pages
+pub const MIN_DEV_SHM_SIZE: u64 = 512 * 1024 * 1024;
+
+/// How the launcher reacts to hosts where Chromium's defaults cannot work
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SandboxPolicy {
+    /// Add `--no-sandbox` and `--disable-dev-shm-usage` where needed
+    Auto,
+    /// Add `--disable-dev-shm-usage` where needed, but refuse to disable the sandbox
+    Strict,
+    /// Do not inspect the host
+    Off,
+}
+
+impl fmt::Display for SandboxPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandboxPolicy::Auto => write!(f, "auto"),
+            SandboxPolicy::Strict => write!(f, "strict"),
+            SandboxPolicy::Off => write!(f, "off"),
+        }
+    }
+}
+
+impl FromStr for SandboxPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(SandboxPolicy::Auto),
+            "strict" => Ok(SandboxPolicy::Strict),
+            "off" => Ok(SandboxPolicy::Off),
+            other => Err(format!("Unknown sandbox policy: {}", other)),
+        }
+    }
+}
+
+/// What the launcher found out about the host it runs on
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostEnvironment {
+    /// The effective uid is 0
+    pub is_root: bool,
+    /// `/.dockerenv`, `/run/.containerenv`, `$container` or a container cgroup was found
+    pub in_container: bool,
+    /// Size of the `/dev/shm` tmpfs, when it is mounted with an explicit size
+    pub dev_shm_size: Option<u64>,
+    /// `kernel.unprivileged_userns_clone` or `user.max_user_namespaces` is 0
+    pub user_namespaces_disabled: bool,
+}
+
+impl HostEnvironment {
+    /// Inspect the current host; everything is `false`/`None` outside Linux
+    pub fn detect() -> Self {
+        if !cfg!(target_os = "linux") {
+            return Self::default();
+        }
+        Self {
+            is_root: effective_uid_is_root(),
+            in_container: in_container(),
+            dev_shm_size: fs::read_to_string("/proc/mounts")
+                .ok()
+                .and_then(|mounts| dev_shm_size(&mounts)),
+            user_namespaces_disabled: ["/proc/sys/kernel/unprivileged_userns_clone", "/proc/sys/user/max_user_namespaces"]
+                .iter()
+                .any(|path| fs::read_to_string(path).is_ok_and(|value| value.trim() == "0")),
+        }
+    }
+
+    /// Why Chromium's sandbox cannot start here, if it cannot
+    pub fn sandbox_blocker(&self) -> Option<String> {
+        if self.is_root {
+            Some("the browser would run as root (uid 0)".to_string())
+        } else if self.user_namespaces_disabled {
+            Some("unprivileged user namespaces are disabled".to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Whether `/dev/shm` is too small for Chromium's shared memory
+    pub fn small_dev_shm(&self) -> bool {
+        self.dev_shm_size.is_some_and(|size| size < MIN_DEV_SHM_SIZE)
+    }
+}
+
+fn effective_uid_is_root() -> bool {
+    // "Uid:\treal\teffective\tsaved\tfs"
+    fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("Uid:"))
+                .and_then(|uids| uids.split_whitespace().nth(1).map(|uid| uid == "0"))
+        })
+        .unwrap_or(false)
+}
+
+fn in_container() -> bool {
+    if Path::new("/.dockerenv").exists() || Path::new("/run/.containerenv").exists() || env::var_os("container").is_some() {
+        return true;
+    }
+    fs::read_to_string("/proc/1/cgroup").is_ok_and(|cgroup| {
+        ["docker", "kubepods", "containerd", "libpod", "lxc"]
+            .iter()
+            .any(|marker| cgroup.contains(marker))
+    })
+}
+
+/// The `size=` of the `/dev/shm` mount in `/proc/mounts` content, in bytes
+pub fn dev_shm_size(mounts: &str) -> Option<u64> {
+    let options = mounts.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        (fields.get(1) == Some(&"/dev/shm")).then(|| fields.get(3).copied()).flatten()
+    })?;
+    let size = options.split(',').find_map(|option| option.strip_prefix("size="))?;
+    let (digits, unit) = size.split_at(size.find(|c: char| !c.is_ascii_digit()).unwrap_or(size.len()));
+    let multiplier = match unit.to_ascii_lowercase().as_str() {
+        "" => 1,
+        "k" => 1024,
+        "m" => 1024 * 1024,
+        "g" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    digits.parse::<u64>().ok().map(|value| value * multiplier)
+}
@@ -0,0 +1,196 @@
+//! Registry of browsers started by the CLI, so later invocations can find them
+
+use serde_json::json;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+#[cfg(windows)]
+use std::process::{Command, Stdio};
+
+/// A browser instance recorded after a successful launch
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstanceRecord {
+    pub pid: u32,
+    pub port: u16,
+    pub browser: String,
+    pub user_data_dir: String,
+    pub ws_url: Option<String>,
+    /// Start time of the process from `process_start_time`, so a reused PID
+    /// is not mistaken for the browser
+    pub start_time: Option<u64>,
+}
+
+impl InstanceRecord {
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "pid": self.pid,
+            "port": self.port,
+            "browser": self.browser,
+            "user_data_dir": self.user_data_dir,
+            "ws_url": self.ws_url,
+            "start_time": self.start_time,
+        })
+    }
+
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        Some(Self {
+            pid: u32::try_from(value["pid"].as_u64()?).ok()?,
+            port: u16::try_from(value["port"].as_u64()?).ok()?,
+            browser: value["browser"].as_str()?.to_string(),
+            user_data_dir: value["user_data_dir"].as_str()?.to_string(),
+            ws_url: value["ws_url"].as_str().map(String::from),
+            start_time: value["start_time"].as_u64(),
+        })
+    }
+
+    /// Whether the recorded process is still the one that was launched
+    pub fn is_running(&self) -> bool {
+        process_is_running(self.pid) && process_start_time(self.pid) == self.start_time
+    }
+}
+
+/// Directory of one JSON file per recorded instance
+pub struct InstanceRegistry {
+    dir: PathBuf,
+}
+
+impl Default for InstanceRegistry {
+    /// `$XDG_RUNTIME_DIR/browser_launcher/instances`, or a per-user directory in the temp dir
+    fn default() -> Self {
+        let runtime_dir = env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute());
+        let base = match runtime_dir {
+            Some(dir) => dir.join("browser_launcher"),
+            #[cfg(unix)]
+            None => env::temp_dir().join(format!("browser_launcher-{}", unsafe { libc::getuid() })),
+            #[cfg(not(unix))]
+            None => env::temp_dir().join("browser_launcher"),
+        };
+        Self::new(base.join("instances"))
+    }
+}
+
+impl InstanceRegistry {
+    /// Create a registry stored in `dir`
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn record_path(&self, pid: u32) -> PathBuf {
+        self.dir.join(format!("{}.json", pid))
+    }
+
+    /// Store a record, replacing any previous record for the same PID
+    pub fn record(&self, instance: &InstanceRecord) -> Result<(), String> {
+        create_private_dir(&self.dir)?;
+        fs::write(self.record_path(instance.pid), instance.to_json().to_string()).map_err(|e| e.to_string())
+    }
+
+    /// Remove the record for `pid`, if any
+    pub fn remove(&self, pid: u32) {
+        let _ = fs::remove_file(self.record_path(pid));
+    }
+
+    /// All records, ordered by PID
+    pub fn list(&self) -> Vec<InstanceRecord> {
+        let mut records: Vec<InstanceRecord> = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+            .filter_map(|content| serde_json::from_str(&content).ok())
+            .filter_map(|value| InstanceRecord::from_json(&value))
+            .collect();
+        records.sort_by_key(|record| record.pid);
+        records
+    }
+
+    /// Records whose process is still alive and was not replaced by another
+    /// one with the same PID; stale records are dropped
+    pub fn running(&self) -> Vec<InstanceRecord> {
+        self.list()
+            .into_iter()
+            .filter(|record| {
+                let alive = record.is_running();
+                if !alive {
+                    self.remove(record.pid);
+                }
+                alive
+            })
+            .collect()
+    }
+}
+
+/// Create `dir` readable by the current user only, refusing one owned by someone else
+fn create_private_dir(dir: &Path) -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let owner = fs::metadata(dir).map_err(|e| e.to_string())?.uid();
+        if owner != unsafe { libc::getuid() } {
+            return Err(format!("{} is owned by another user", dir.display()));
+        }
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))
+}
+
+/// Start time of the process `pid` in clock ticks after boot (field 22 of
+/// `/proc/<pid>/stat`); `None` where there is no `/proc`
+pub fn process_start_time(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name in field 2 may itself contain spaces and parentheses
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+/// Check whether a process with `pid` exists
+pub fn process_is_running(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        match crate::utils::send_signal(pid, 0) {
+            Ok(()) => true,
+            // The process exists but belongs to another user
+            Err(e) => e.raw_os_error() == Some(libc::EPERM),
+        }
+    }
+    #[cfg(windows)]
+    {
+        Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+            .stderr(Stdio::null())
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+            .unwrap_or(false)
+    }
+}
+
+/// Ask the process with `pid` to terminate
+pub fn terminate_process(pid: u32) -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        crate::utils::send_signal(pid, libc::SIGTERM)
+            .map_err(|e| format!("Failed to terminate process {}: {}", pid, e))
+    }
+    #[cfg(windows)]
+    {
+        let status = Command::new("taskkill")
+            .args(["/PID", &pid.to_string(), "/T", "/F"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        match status {
+            Ok(status) if status.success() => Ok(()),
+            Ok(_) => Err(format!("Failed to terminate process {}", pid)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
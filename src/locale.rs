@@ -0,0 +1,74 @@
+//! Locale and timezone validation
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+/// Where the IANA timezone database usually lives
+const ZONEINFO_DIRS: &[&str] = &["/usr/share/zoneinfo", "/usr/lib/zoneinfo", "/usr/share/lib/zoneinfo"];
+/// Magic bytes at the start of every compiled zone file
+const TZIF_MAGIC: &[u8] = b"TZif";
+
+/// Check a BCP 47 language tag such as `de`, `de-DE` or `zh-Hant-TW`
+pub fn validate_locale(locale: &str) -> Result<(), String> {
+    let mut subtags = locale.split('-');
+    let language = subtags.next().unwrap_or_default();
+    let valid = (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric()));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid locale '{}' (expected a language tag such as en-US or de)", locale))
+    }
+}
+
+/// `Accept-Language` list for `locale`: the tag itself, then its bare language
+pub fn accept_languages(locale: &str) -> String {
+    match locale.split_once('-') {
+        Some((language, _)) => format!("{},{}", locale, language),
+        None => locale.to_string(),
+    }
+}
+
+/// Check that `timezone` names a zone in the system zoneinfo database, e.g. `Europe/Berlin`.
+///
+/// Returns a warning instead when there is no database to check against.
+pub fn validate_timezone(timezone: &str) -> Result<Option<String>, String> {
+    let valid_name = !timezone.is_empty()
+        && !timezone.starts_with('/')
+        && !timezone.split('/').any(|part| part.is_empty() || part == "." || part == "..")
+        && timezone
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '+'));
+    if !valid_name {
+        return Err(format!("Invalid timezone '{}' (expected an IANA name such as Europe/Berlin)", timezone));
+    }
+
+    let dirs = zoneinfo_dirs();
+    if dirs.is_empty() {
+        return Ok(Some(format!("No zoneinfo database found, cannot validate timezone '{}'", timezone)));
+    }
+    let is_zone = |dir: &PathBuf| {
+        let mut magic = [0u8; 4];
+        File::open(dir.join(timezone))
+            .and_then(|mut file| file.read_exact(&mut magic))
+            .is_ok_and(|_| magic == TZIF_MAGIC)
+    };
+    if dirs.iter().any(is_zone) {
+        Ok(None)
+    } else {
+        Err(format!("Unknown timezone '{}' (not found in {})", timezone, dirs[0].display()))
+    }
+}
+
+/// Zoneinfo directories present on this system, `TZDIR` first
+fn zoneinfo_dirs() -> Vec<PathBuf> {
+    env::var_os("TZDIR")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(ZONEINFO_DIRS.iter().map(PathBuf::from))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
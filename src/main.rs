@@ -1,197 +1,336 @@
-use browser_launcher::{Launcher, Options, BrowserType};
-use clap::{Parser, ValueEnum};
-
-#[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-struct Args {
-    /// Sets the starting URL
-    #[arg(long)]
-    starting_url: Option<String>,
-
-    /// Sets browser flags (comma-separated)
-    #[arg(long)]
-    browser_flags: Option<String>,
-
-    /// Sets the port for remote debugging
-    #[arg(long)]
-    port: Option<u16>,
-
-    /// Browser type to launch
-    #[arg(long, value_enum)]
-    browser: Option<BrowserTypeArg>,
-
-    /// Run in headless mode
-    #[arg(long)]
-    headless: bool,
-
-    /// Window size (format: WIDTHxHEIGHT)
-    #[arg(long)]
-    window_size: Option<String>,
-
-    /// Run in incognito mode
-    #[arg(long)]
-    incognito: bool,
-
-    /// Disable GPU acceleration
-    #[arg(long)]
-    disable_gpu: bool,
-
-    /// Disable sandbox (use with caution)
-    #[arg(long)]
-    no_sandbox: bool,
-
-    /// Disable web security
-    #[arg(long)]
-    disable_web_security: bool,
-
-    /// Allow running insecure content
-    #[arg(long)]
-    allow_insecure_content: bool,
-
-    /// Ignore SSL/HTTPS errors
-    #[arg(long)]
-    ignore_ssl_errors: bool,
-
-    /// Disable browser extensions
-    #[arg(long)]
-    disable_extensions: bool,
-
-    /// Disable plugins
-    #[arg(long)]
-    disable_plugins: bool,
-
-    /// Disable images
-    #[arg(long)]
-    disable_images: bool,
-
-    /// Disable JavaScript
-    #[arg(long)]
-    disable_javascript: bool,
-
-    /// Set custom user agent
-    #[arg(long)]
-    user_agent: Option<String>,
-
-    /// Set proxy server
-    #[arg(long)]
-    proxy_server: Option<String>,
-
-    /// Set host resolver rules
-    #[arg(long)]
-    host_resolver_rules: Option<String>,
-
-    /// User data directory
-    #[arg(long)]
-    user_data_dir: Option<String>,
-
-    /// Additional browser arguments (comma-separated)
-    #[arg(long)]
-    additional_args: Option<String>,
+use browser_launcher::cli::{Args, Cli, Commands, KillArgs, OutputFormat, TargetsArgs, VersionArgs};
+use browser_launcher::devtools;
+use browser_launcher::instances::{process_start_time, terminate_process, InstanceRecord, InstanceRegistry};
+use browser_launcher::{Browser, BrowserFinder, BrowserType, LaunchedBrowser, Launcher, Protocol};
+use clap::Parser;
+use serde_json::{json, Value};
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Set by the SIGINT handler `--handle-sigint` installs
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+fn main() {
+    // Parse command line arguments
+    let cli = Cli::parse();
+    let output = cli.output;
+
+    let result = match cli.command {
+        None => run_launch(cli.launch, output),
+        Some(Commands::Launch(args)) => run_launch(args, output),
+        Some(Commands::Find) => run_find(output),
+        Some(Commands::Version(args)) => run_version(args, output),
+        Some(Commands::Flags(args)) => run_flags(args, output),
+        Some(Commands::Kill(args)) => run_kill(args, output),
+        Some(Commands::Targets(args)) => run_targets(args, output),
+    };
+
+    if let Err(e) = result {
+        match output {
+            OutputFormat::Text => eprintln!("Error: {}", e),
+            OutputFormat::Json => emit("error", json!({ "message": e })),
+        }
+        std::process::exit(1);
+    }
 }
 
-#[derive(Clone, Debug, ValueEnum)]
-enum BrowserTypeArg {
-    Chrome,
-    Chromium,
-    Edge,
-    Brave,
-    Opera,
-    Vivaldi,
+/// Print one newline-delimited JSON event
+fn emit(event: &str, fields: Value) {
+    let mut line = json!({ "event": event });
+    if let (Some(line), Value::Object(fields)) = (line.as_object_mut(), fields) {
+        line.extend(fields);
+    }
+    println!("{}", line);
 }
 
-fn main() {
-    // Parse command line arguments
-    let args = Args::parse();
+/// Report a problem that does not stop the command
+fn warn(output: OutputFormat, message: &str) {
+    match output {
+        OutputFormat::Text => eprintln!("Warning: {}", message),
+        OutputFormat::Json => emit("warning", json!({ "message": message })),
+    }
+}
 
-    // Initialize Options and populate from parsed arguments
-    let mut options = Options::default();
+fn exit_fields(status: &ExitStatus) -> Value {
+    #[cfg(unix)]
+    let signal = std::os::unix::process::ExitStatusExt::signal(status);
+    #[cfg(not(unix))]
+    let signal: Option<i32> = None;
+    json!({ "code": status.code(), "signal": signal })
+}
+
+fn run_launch(args: Args, output: OutputFormat) -> Result<(), String> {
+    let dry_run = args.dry_run;
 
-    if let Some(url) = args.starting_url {
-        options.starting_url = Some(url);
+    // Initialize the Launcher with the parsed options
+    let mut launcher = Launcher::try_new(args.into_options()?)?;
+    if dry_run {
+        let plan = launcher.plan()?;
+        match output {
+            OutputFormat::Text => {
+                print!("{}", plan);
+                for warning in &plan.warnings {
+                    warn(output, warning);
+                }
+            }
+            // The plan lists its warnings itself
+            OutputFormat::Json => println!("{}", plan.to_json()),
+        }
+        return Ok(());
     }
 
-    if let Some(flags) = args.browser_flags {
-        options.browser_flags = Some(flags.split(',').map(String::from).collect());
+    if launcher.handle_sigint() {
+        install_sigint_handler()?;
+    }
+    let mut launched_browser = launcher.launch()?;
+    if output == OutputFormat::Text {
+        println!("Launched browser with PID: {}", launched_browser.pid);
+        for extension in &launched_browser.extensions {
+            println!("Loaded extension: {}", extension);
+        }
     }
 
-    if let Some(port) = args.port {
-        options.port = Some(port);
+    let registry = InstanceRegistry::default();
+    let endpoint = launcher.wait_for_endpoint(&mut launched_browser);
+    if output == OutputFormat::Json {
+        emit(
+            "launched",
+            json!({
+                "pid": launched_browser.pid,
+                "port": launched_browser.port,
+                "ws_url": launched_browser.ws_url,
+                "protocol": launched_browser.protocol.name(),
+                "user_data_dir": launched_browser.user_data_dir,
+                "extensions": launched_browser
+                    .extensions
+                    .iter()
+                    .map(|extension| json!({ "id": extension.id, "path": extension.path }))
+                    .collect::<Vec<_>>(),
+            }),
+        );
+    }
+    for warning in &launched_browser.warnings {
+        warn(output, warning);
     }
 
-    // Browser type
-    if let Some(browser) = args.browser {
-        options.browser = Some(match browser {
-            BrowserTypeArg::Chrome => BrowserType::Chrome,
-            BrowserTypeArg::Chromium => BrowserType::Chromium,
-            BrowserTypeArg::Edge => BrowserType::Edge,
-            BrowserTypeArg::Brave => BrowserType::Brave,
-            BrowserTypeArg::Opera => BrowserType::Opera,
-            BrowserTypeArg::Vivaldi => BrowserType::Vivaldi,
-        });
+    match endpoint.and_then(|_| launcher.wait_until_ready(&mut launched_browser)) {
+        Ok(()) => {
+            match output {
+                OutputFormat::Text => {
+                    if let Some(ref ws_url) = launched_browser.ws_url {
+                        match launched_browser.protocol {
+                            Protocol::Cdp => println!("DevTools listening on {}", ws_url),
+                            Protocol::Bidi => println!("WebDriver BiDi listening on {}", ws_url),
+                        }
+                    }
+                }
+                OutputFormat::Json => emit(
+                    "ready",
+                    json!({
+                        "port": launched_browser.port,
+                        "ws_url": launched_browser.ws_url,
+                        "protocol": launched_browser.protocol.name(),
+                    }),
+                ),
+            }
+            let record = InstanceRecord {
+                pid: launched_browser.pid,
+                port: launched_browser.port,
+                browser: launcher.browser_type().name().to_string(),
+                user_data_dir: launched_browser.user_data_dir.clone(),
+                ws_url: launched_browser.ws_url.clone(),
+                start_time: process_start_time(launched_browser.pid),
+            };
+            if let Err(e) = registry.record(&record) {
+                warn(output, &format!("failed to record browser instance: {}", e));
+            }
+        }
+        Err(e) => warn(output, &e),
     }
 
-    // Launch options
-    options.headless = Some(args.headless);
-    options.incognito = Some(args.incognito);
-    options.disable_gpu = Some(args.disable_gpu);
-    options.no_sandbox = Some(args.no_sandbox);
-    options.disable_web_security = Some(args.disable_web_security);
-    options.allow_running_insecure_content = Some(args.allow_insecure_content);
-    options.ignore_ssl_errors = Some(args.ignore_ssl_errors);
-    options.disable_extensions = Some(args.disable_extensions);
-    options.disable_plugins = Some(args.disable_plugins);
-    options.disable_images = Some(args.disable_images);
-    options.disable_javascript = Some(args.disable_javascript);
-
-    // Window size parsing
-    if let Some(window_size) = args.window_size {
-        if let Some((width, height)) = parse_window_size(&window_size) {
-            options.window_size = Some((width, height));
-        } else {
-            eprintln!("Invalid window size format. Expected WIDTHxHEIGHT (e.g., 1920x1080)");
-            std::process::exit(1);
+    let waited = match launcher.handle_sigint() {
+        true => wait_or_interrupt(&mut launched_browser),
+        false => launched_browser.wait(),
+    };
+    registry.remove(launched_browser.pid);
+    let status = waited.map_err(|e| e.to_string())?;
+    match output {
+        OutputFormat::Text => println!("Browser process has exited."),
+        OutputFormat::Json => emit("exited", exit_fields(&status)),
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+extern "C" fn on_sigint(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Catch SIGINT so `wait_or_interrupt` can shut the browser down and clean up
+fn install_sigint_handler() -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        let handler = on_sigint as extern "C" fn(libc::c_int);
+        if unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) } == libc::SIG_ERR {
+            return Err(format!("Failed to install a SIGINT handler: {}", std::io::Error::last_os_error()));
         }
+        Ok(())
     }
+    #[cfg(not(unix))]
+    Err("--handle-sigint is only supported on Unix".to_string())
+}
 
-    // Additional options
-    options.user_agent = args.user_agent;
-    options.proxy_server = args.proxy_server;
-    options.host_resolver_rules = args.host_resolver_rules;
-    options.user_data_dir = args.user_data_dir;
+/// Wait for the browser to exit, killing it once SIGINT arrives
+fn wait_or_interrupt(browser: &mut LaunchedBrowser) -> std::io::Result<ExitStatus> {
+    loop {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            browser.kill()?;
+            return browser.wait();
+        }
+        if browser.process.try_wait()?.is_some() {
+            return browser.wait();
+        }
+        thread::sleep(EXIT_POLL_INTERVAL);
+    }
+}
+
+fn browser_json(browser: &mut Browser) -> Value {
+    json!({
+        "name": browser.name(),
+        "path": browser.executable_path,
+        "version": browser.get_version(),
+    })
+}
 
-    if let Some(additional_args) = args.additional_args {
-        options.additional_args = Some(additional_args.split(',').map(String::from).collect());
+fn print_browser(browser: &mut Browser) {
+    let version = browser.get_version().unwrap_or_else(|| "unknown".to_string());
+    println!("{}\t{}\t{}", browser.name(), version, browser.executable_path);
+}
+
+fn run_find(output: OutputFormat) -> Result<(), String> {
+    let mut browsers = BrowserFinder::all_browsers().find_all();
+    match output {
+        OutputFormat::Text => {
+            if browsers.is_empty() {
+                return Err("No supported browsers found on this system".to_string());
+            }
+            for browser in &mut browsers {
+                print_browser(browser);
+            }
+        }
+        OutputFormat::Json => {
+            let browsers: Vec<Value> = browsers.iter_mut().map(browser_json).collect();
+            println!("{}", Value::Array(browsers));
+        }
     }
+    Ok(())
+}
 
-    // Initialize the Launcher with the parsed options
-    let mut launcher = Launcher::new(options);
-
-    match launcher.launch() {
-        Ok(mut launched_browser) => {
-            println!("Launched browser with PID: {}", launched_browser.pid);
-            let _ = launched_browser
-                .process
-                .wait()
-                .map_err(|e: std::io::Error| e.to_string())
-                .unwrap();
-            println!("Browser process has exited.");
+fn run_version(args: VersionArgs, output: OutputFormat) -> Result<(), String> {
+    let browser_type = args.browser.map(BrowserType::from).unwrap_or(BrowserType::Chrome);
+    let mut browser = match args.browser_path {
+        Some(path) => Browser::new(browser_type, path),
+        None => BrowserFinder::new(vec![browser_type.clone()])
+            .find_first()
+            .ok_or_else(|| format!("{} browser not found on this system", browser_type.name()))?,
+    };
+    let version = browser
+        .get_version()
+        .ok_or_else(|| format!("Could not determine the version of {}", browser.executable_path))?;
+    match output {
+        OutputFormat::Text => println!("{} {}", browser.name(), version),
+        OutputFormat::Json => println!("{}", browser_json(&mut browser)),
+    }
+    Ok(())
+}
+
+fn run_flags(args: Args, output: OutputFormat) -> Result<(), String> {
+    let launcher = Launcher::try_new(args.into_options()?)?;
+    let executable = launcher.browser_executable().unwrap_or_else(|e| {
+        warn(output, &e);
+        launcher.browser_type().executables()[0].to_string()
+    });
+    match output {
+        OutputFormat::Text => {
+            println!("{}", executable);
+            for arg in launcher.command_args() {
+                println!("  {}", arg);
+            }
         }
-        Err(e) => {
-            eprintln!("Error launching browser: {}", e);
-            std::process::exit(1);
+        OutputFormat::Json => {
+            println!("{}", json!({ "executable": executable, "args": launcher.command_args() }));
+        }
+    }
+    Ok(())
+}
+
+fn run_kill(args: KillArgs, output: OutputFormat) -> Result<(), String> {
+    let registry = InstanceRegistry::default();
+    let instances: Vec<InstanceRecord> = registry
+        .running()
+        .into_iter()
+        .filter(|record| args.pid.is_none_or(|pid| record.pid == pid))
+        .collect();
+
+    if instances.is_empty() {
+        return Err(match args.pid {
+            Some(pid) => format!("No running browser instance recorded with PID {}", pid),
+            None => "No running browser instances recorded".to_string(),
+        });
+    }
+
+    for record in instances {
+        terminate_process(record.pid)?;
+        registry.remove(record.pid);
+        match output {
+            OutputFormat::Text => println!("Stopped {} (PID {})", record.browser, record.pid),
+            OutputFormat::Json => emit("killed", json!({ "pid": record.pid, "browser": record.browser })),
         }
     }
+    Ok(())
 }
 
-fn parse_window_size(size: &str) -> Option<(u32, u32)> {
-    let parts: Vec<&str> = size.split('x').collect();
-    if parts.len() == 2 {
-        if let (Ok(width), Ok(height)) = (parts[0].parse::<u32>(), parts[1].parse::<u32>()) {
-            Some((width, height))
-        } else {
-            None
+fn run_targets(args: TargetsArgs, output: OutputFormat) -> Result<(), String> {
+    let port = match args.port {
+        Some(port) => port,
+        None => {
+            let running = InstanceRegistry::default().running();
+            let record = match args.pid {
+                Some(pid) => running.into_iter().find(|record| record.pid == pid),
+                None if running.len() > 1 => {
+                    return Err("Several browser instances are running, choose one with --pid".to_string())
+                }
+                None => running.into_iter().next(),
+            };
+            record.ok_or("No running browser instance found, pass --port or --pid")?.port
+        }
+    };
+
+    let targets = devtools::list_targets(port)?;
+    match output {
+        OutputFormat::Text => {
+            for target in targets {
+                println!("{}\t{}\t{}\t{}", target.id, target.target_type, target.title, target.url);
+            }
+        }
+        OutputFormat::Json => {
+            let targets: Vec<Value> = targets
+                .iter()
+                .map(|target| {
+                    json!({
+                        "id": target.id,
+                        "type": target.target_type,
+                        "title": target.title,
+                        "url": target.url,
+                        "ws_url": target.web_socket_debugger_url,
+                    })
+                })
+                .collect();
+            println!("{}", Value::Array(targets));
         }
-    } else {
-        None
     }
+    Ok(())
 }
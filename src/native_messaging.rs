@@ -0,0 +1,229 @@
+//! Native messaging host manifests for Chromium extensions
+
+use crate::browser::BrowserType;
+use serde_json::{json, Value};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const HOSTS_DIR: &str = "NativeMessagingHosts";
+const EXTENSION_ORIGIN_PREFIX: &str = "chrome-extension://";
+
+/// Where a host manifest is installed
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum NativeHostLocation {
+    /// `NativeMessagingHosts` inside the launcher's `user_data_dir`
+    #[default]
+    Profile,
+    /// The browser's per-user directory, such as `~/.config/google-chrome/NativeMessagingHosts`,
+    /// which Chromium reads when it runs with its default user data directory
+    User,
+}
+
+impl fmt::Display for NativeHostLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NativeHostLocation::Profile => write!(f, "profile"),
+            NativeHostLocation::User => write!(f, "user"),
+        }
+    }
+}
+
+impl FromStr for NativeHostLocation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "profile" => Ok(NativeHostLocation::Profile),
+            "user" => Ok(NativeHostLocation::User),
+            _ => Err(format!("Invalid native messaging host location '{}' (expected profile or user)", s)),
+        }
+    }
+}
+
+/// A native messaging host: the helper program extensions connect to
+#[derive(Debug, Clone, PartialEq)]
+pub struct NativeMessagingHost {
+    /// Host name extensions pass to `chrome.runtime.connectNative`, e.g. `com.example.helper`
+    pub name: String,
+    pub description: Option<String>,
+    /// Absolute path of the helper executable
+    pub path: PathBuf,
+    /// `chrome-extension://<id>/` origins allowed to connect; empty allows
+    /// every extension loaded through `Options::extensions`
+    pub allowed_origins: Vec<String>,
+    pub location: NativeHostLocation,
+}
+
+impl NativeMessagingHost {
+    pub fn new<P: Into<PathBuf>>(name: &str, path: P) -> Self {
+        Self {
+            name: name.to_string(),
+            description: None,
+            path: path.into(),
+            allowed_origins: Vec::new(),
+            location: NativeHostLocation::default(),
+        }
+    }
+
+    /// Allow the extension with ID `id` to connect
+    pub fn allow_extension(mut self, id: &str) -> Self {
+        self.allowed_origins.push(format!("{}{}/", EXTENSION_ORIGIN_PREFIX, id));
+        self
+    }
+
+    pub fn with_location(mut self, location: NativeHostLocation) -> Self {
+        self.location = location;
+        self
+    }
+
+    /// Check the name, helper path and origins the way Chromium does
+    pub fn validate(&self) -> Result<(), String> {
+        let valid_name = !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '.')
+            && !self.name.starts_with('.')
+            && !self.name.ends_with('.')
+            && !self.name.contains("..");
+        if !valid_name {
+            return Err(format!(
+                "Invalid native messaging host name '{}' (lowercase letters, digits, '_' and single '.' only)",
+                self.name
+            ));
+        }
+        if !self.path.is_absolute() {
+            return Err(format!("Native messaging host path must be absolute: {}", self.path.display()));
+        }
+        if !self.path.is_file() {
+            return Err(format!("Native messaging host executable not found: {}", self.path.display()));
+        }
+        for origin in &self.allowed_origins {
+            let id = origin
+                .strip_prefix(EXTENSION_ORIGIN_PREFIX)
+                .and_then(|rest| rest.strip_suffix('/'))
+                .unwrap_or_default();
+            if id.len() != 32 || !id.chars().all(|c| ('a'..='p').contains(&c)) {
+                return Err(format!(
+                    "Invalid allowed origin '{}' (expected chrome-extension://<extension id>/)",
+                    origin
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The JSON manifest, with `allowed_origins` replaced by `origins`
+    pub fn manifest(&self, origins: &[String]) -> Value {
+        json!({
+            "name": self.name,
+            "description": self
+                .description
+                .clone()
+                .unwrap_or_else(|| format!("Native messaging host {}", self.name)),
+            "path": self.path,
+            "type": "stdio",
+            "allowed_origins": origins,
+        })
+    }
+
+    /// Path of the manifest for `browser`, given the launcher's `user_data_dir`
+    pub fn manifest_path(&self, browser: &BrowserType, user_data_dir: &Path) -> Result<PathBuf, String> {
+        let dir = match self.location {
+            NativeHostLocation::Profile => user_data_dir.join(HOSTS_DIR),
+            NativeHostLocation::User => user_hosts_dir(browser)?,
+        };
+        Ok(dir.join(format!("{}.json", self.name)))
+    }
+}
+
+impl FromStr for NativeMessagingHost {
+    type Err = String;
+
+    /// Parse `[user:|profile:]NAME=PATH`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (location, host) = match s.split_once(':') {
+            Some((location, host)) if !location.contains('=') => (location.parse()?, host),
+            _ => (NativeHostLocation::default(), s),
+        };
+        let (name, path) = host
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid native messaging host '{}' (expected NAME=PATH)", s))?;
+        Ok(NativeMessagingHost::new(name.trim(), path.trim()).with_location(location))
+    }
+}
+
+/// The per-user `NativeMessagingHosts` directory of a Chromium-based browser
+fn user_hosts_dir(browser: &BrowserType) -> Result<PathBuf, String> {
+    let unsupported = || format!("{} has no known per-user native messaging host directory", browser.name());
+    let home = home::home_dir().ok_or("Cannot find the home directory")?;
+
+    let (base, dir) = if cfg!(target_os = "linux") {
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .unwrap_or_else(|| home.join(".config"));
+        let dir = match browser {
+            BrowserType::Chrome => "google-chrome",
+            BrowserType::ChromeCanary => "google-chrome-unstable",
+            BrowserType::Chromium => "chromium",
+            BrowserType::Edge => "microsoft-edge",
+            BrowserType::Brave => "BraveSoftware/Brave-Browser",
+            BrowserType::Opera => "opera",
+            BrowserType::Vivaldi => "vivaldi",
+            _ => return Err(unsupported()),
+        };
+        (base, dir)
+    } else if cfg!(target_os = "macos") {
+        let dir = match browser {
+            BrowserType::Chrome => "Google/Chrome",
+            BrowserType::ChromeCanary => "Google/Chrome Canary",
+            BrowserType::Chromium => "Chromium",
+            BrowserType::Edge => "Microsoft Edge",
+            BrowserType::Brave => "BraveSoftware/Brave-Browser",
+            BrowserType::Opera => "com.operasoftware.Opera",
+            BrowserType::Vivaldi => "Vivaldi",
+            _ => return Err(unsupported()),
+        };
+        (home.join("Library/Application Support"), dir)
+    } else {
+        return Err("Native messaging hosts are registered in the Windows registry, which is not supported".to_string());
+    };
+    Ok(base.join(dir).join(HOSTS_DIR))
+}
+
+/// An installed manifest; restores whatever was there before when dropped
+#[derive(Debug)]
+pub struct InstalledNativeHost {
+    pub manifest_path: PathBuf,
+    previous: Option<Vec<u8>>,
+}
+
+impl InstalledNativeHost {
+    /// Write `manifest` to `manifest_path`, keeping any existing file for `Drop`
+    pub fn install(manifest_path: &Path, manifest: &Value) -> Result<Self, String> {
+        if let Some(parent) = manifest_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let previous = fs::read(manifest_path).ok();
+        let content = serde_json::to_string_pretty(manifest).map_err(|e| e.to_string())?;
+        fs::write(manifest_path, content)
+            .map_err(|e| format!("Failed to write {}: {}", manifest_path.display(), e))?;
+        Ok(Self {
+            manifest_path: manifest_path.to_path_buf(),
+            previous,
+        })
+    }
+}
+
+impl Drop for InstalledNativeHost {
+    fn drop(&mut self) {
+        let _ = match self.previous.take() {
+            Some(previous) => fs::write(&self.manifest_path, previous),
+            None => fs::remove_file(&self.manifest_path),
+        };
+    }
+}
@@ -0,0 +1,363 @@
+//! Launch plans: everything `Launcher::launch` would do, without spawning anything
+
+use crate::cgroup::ResourceLimits;
+use crate::extensions::Extension;
+use crate::proxy::ProxyServer;
+use crate::sched::ProcessControls;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// Where a command line switch or environment variable came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlagSource {
+    /// Built into the launcher (`DEFAULT_FLAGS`, platform flags, default values)
+    Default,
+    /// An `Options` field set in code or on the command line
+    Option(&'static str),
+    /// An `Options` field filled from a `BROWSER_LAUNCHER_*` variable, or a
+    /// setting detected from a session variable such as `WAYLAND_DISPLAY`
+    Env(String),
+    /// An `Options` field filled from the config file
+    ConfigFile(String),
+    /// A bundled preset
+    Preset(String),
+    /// Added because of something detected on the host, such as running as root
+    Host(String),
+    /// `browser_flags` or `additional_args`, passed through verbatim
+    User,
+}
+
+impl fmt::Display for FlagSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlagSource::Default => write!(f, "default"),
+            FlagSource::Option(field) => write!(f, "option {}", field),
+            FlagSource::Env(var) => write!(f, "env {}", var),
+            FlagSource::ConfigFile(path) => write!(f, "config file {}", path),
+            FlagSource::Preset(name) => write!(f, "preset {}", name),
+            FlagSource::Host(reason) => write!(f, "host: {}", reason),
+            FlagSource::User => write!(f, "user flag"),
+        }
+    }
+}
+
+impl FlagSource {
+    /// JSON form: `{"type": "option", "name": "headless"}`
+    pub fn to_json(&self) -> Value {
+        match self {
+            FlagSource::Default => json!({ "type": "default" }),
+            FlagSource::Option(field) => json!({ "type": "option", "name": field }),
+            FlagSource::Env(var) => json!({ "type": "env", "name": var }),
+            FlagSource::ConfigFile(path) => json!({ "type": "config_file", "name": path }),
+            FlagSource::Preset(name) => json!({ "type": "preset", "name": name }),
+            FlagSource::Host(reason) => json!({ "type": "host", "name": reason }),
+            FlagSource::User => json!({ "type": "user" }),
+        }
+    }
+}
+
+/// One browser argument and its origin
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedArg {
+    pub value: String,
+    pub source: FlagSource,
+}
+
+impl PlannedArg {
+    pub fn new<S: Into<String>>(value: S, source: FlagSource) -> Self {
+        Self {
+            value: value.into(),
+            source,
+        }
+    }
+}
+
+/// One environment variable set for the browser process and its origin
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedEnvVar {
+    pub name: String,
+    pub value: String,
+    pub source: FlagSource,
+}
+
+/// A filesystem change made to the profile before the browser starts
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProfileAction {
+    /// Create the user data directory
+    CreateDir(PathBuf),
+    /// Remove a stale file such as `DevToolsActivePort`
+    RemoveFile(PathBuf),
+    /// Merge `prefs` (dotted keys) into a Chromium `Preferences` file
+    WritePreferences {
+        path: PathBuf,
+        prefs: HashMap<String, Value>,
+    },
+    /// Set `prefs` as `user_pref(...)` lines in a Firefox `user.js` file
+    WriteUserJs {
+        path: PathBuf,
+        prefs: HashMap<String, Value>,
+    },
+}
+
+impl fmt::Display for ProfileAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileAction::CreateDir(path) => write!(f, "create directory {}", path.display()),
+            ProfileAction::RemoveFile(path) => write!(f, "remove {}", path.display()),
+            ProfileAction::WritePreferences { path, prefs } | ProfileAction::WriteUserJs { path, prefs } => {
+                let mut keys: Vec<&String> = prefs.keys().collect();
+                keys.sort();
+                let keys: Vec<&str> = keys.iter().map(|key| key.as_str()).collect();
+                write!(f, "write {} ({})", path.display(), keys.join(", "))
+            }
+        }
+    }
+}
+
+impl ProfileAction {
+    /// Perform the action
+    pub fn apply(&self) -> Result<(), String> {
+        match self {
+            ProfileAction::CreateDir(path) => fs::create_dir_all(path)
+                .map_err(|e| format!("Failed to create {}: {}", path.display(), e)),
+            ProfileAction::RemoveFile(path) => {
+                let _ = fs::remove_file(path);
+                Ok(())
+            }
+            ProfileAction::WritePreferences { path, prefs } => write_preferences(path, prefs),
+            ProfileAction::WriteUserJs { path, prefs } => write_user_js(path, prefs),
+        }
+    }
+}
+
+/// Write `prefs` into a Firefox `user.js`, keeping unrelated existing lines
+fn write_user_js(path: &PathBuf, prefs: &HashMap<String, Value>) -> Result<(), String> {
+    let existing = fs::read_to_string(path).unwrap_or_default();
+    let mut lines: Vec<String> = existing
+        .lines()
+        .filter(|line| {
+            !prefs
+                .keys()
+                .any(|key| line.trim_start().starts_with(&format!("user_pref({},", Value::String(key.clone()))))
+        })
+        .map(String::from)
+        .collect();
+
+    let mut keys: Vec<&String> = prefs.keys().collect();
+    keys.sort();
+    for key in keys {
+        // JSON literals are valid JavaScript literals
+        lines.push(format!("user_pref({}, {});", Value::String(key.clone()), prefs[key]));
+    }
+
+    fs::write(path, lines.join("\n") + "\n").map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Merge dotted preference keys into the JSON file at `path`
+fn write_preferences(path: &PathBuf, prefs: &HashMap<String, Value>) -> Result<(), String> {
+    let mut root = fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .filter(Value::is_object)
+        .unwrap_or_else(|| Value::Object(Map::new()));
+
+    for (key, value) in prefs {
+        insert_dotted(&mut root, key, value.clone());
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::write(path, root.to_string()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Set `a.b.c` in `root` to `value`, creating intermediate objects
+pub fn insert_dotted(root: &mut Value, key: &str, value: Value) {
+    let mut current = root;
+    let mut parts = key.split('.').peekable();
+    while let Some(part) = parts.next() {
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+        let object = current.as_object_mut().unwrap();
+        if parts.peek().is_none() {
+            object.insert(part.to_string(), value);
+            return;
+        }
+        current = object.entry(part.to_string()).or_insert_with(|| Value::Object(Map::new()));
+    }
+}
+
+/// Everything a launch would do: executable, arguments, environment and profile changes
+#[derive(Debug, Clone)]
+pub struct LaunchPlan {
+    /// Command the browser is started under, such as bwrap; empty when run directly
+    pub wrapper: Vec<PlannedArg>,
+    pub executable: String,
+    pub args: Vec<PlannedArg>,
+    pub env: Vec<PlannedEnvVar>,
+    pub profile_actions: Vec<ProfileAction>,
+    /// Screen size of the Xvfb display started for the browser, if any
+    pub virtual_display: Option<(u32, u32)>,
+    /// Limits of the cgroup the browser tree is moved into, if any
+    pub resource_limits: Option<ResourceLimits>,
+    /// Niceness, CPU affinity and OOM score applied before exec
+    pub process_controls: ProcessControls,
+    /// Local port and upstream of the proxy forwarder adding credentials, if any;
+    /// the port is 0 until `launch` starts the forwarder on a free one
+    pub proxy_forwarder: Option<(u16, ProxyServer)>,
+    /// Unpacked extensions loaded into the browser, with their IDs
+    pub extensions: Vec<Extension>,
+    /// Native messaging host manifests installed for the launch, by path
+    pub native_messaging_hosts: Vec<(PathBuf, Value)>,
+    /// URLs opened over DevTools once the browser is ready, in app and kiosk mode
+    pub deferred_urls: Vec<String>,
+    /// Problems that do not stop the launch, for the caller to report
+    pub warnings: Vec<String>,
+}
+
+impl LaunchPlan {
+    /// The plain argument list passed to the browser
+    pub fn arg_values(&self) -> Vec<String> {
+        self.args.iter().map(|arg| arg.value.clone()).collect()
+    }
+
+    /// JSON form of the plan, for machine-readable output
+    pub fn to_json(&self) -> Value {
+        json!({
+            "wrapper": self
+                .wrapper
+                .iter()
+                .map(|arg| json!({ "value": arg.value, "source": arg.source.to_json() }))
+                .collect::<Vec<Value>>(),
+            "executable": self.executable,
+            "args": self
+                .args
+                .iter()
+                .map(|arg| json!({ "value": arg.value, "source": arg.source.to_json() }))
+                .collect::<Vec<Value>>(),
+            "env": self
+                .env
+                .iter()
+                .map(|var| json!({ "name": var.name, "value": var.value, "source": var.source.to_json() }))
+                .collect::<Vec<Value>>(),
+            "profile_actions": self
+                .profile_actions
+                .iter()
+                .map(|action| Value::String(action.to_string()))
+                .collect::<Vec<Value>>(),
+            "virtual_display": self
+                .virtual_display
+                .map(|(width, height)| json!({ "width": width, "height": height })),
+            "resource_limits": self.resource_limits.as_ref().map(|limits| {
+                json!({
+                    "memory_max": limits.memory_max,
+                    "cpu_quota": limits.cpu_quota,
+                    "pids_max": limits.pids_max,
+                })
+            }),
+            "process_controls": {
+                "nice": self.process_controls.nice,
+                "cpu_affinity": self.process_controls.cpu_affinity,
+                "oom_score_adj": self.process_controls.oom_score_adj,
+            },
+            "proxy_forwarder": self.proxy_forwarder.as_ref().map(|(port, upstream)| {
+                json!({
+                    "port": port,
+                    "upstream": upstream.to_string(),
+                    "username": upstream.credentials.as_ref().map(|credentials| &credentials.username),
+                })
+            }),
+            "extensions": self
+                .extensions
+                .iter()
+                .map(|extension| json!({ "id": extension.id, "path": extension.path }))
+                .collect::<Vec<Value>>(),
+            "native_messaging_hosts": self
+                .native_messaging_hosts
+                .iter()
+                .map(|(path, manifest)| json!({ "path": path, "manifest": manifest }))
+                .collect::<Vec<Value>>(),
+            "deferred_urls": self.deferred_urls,
+            "warnings": self.warnings,
+        })
+    }
+}
+
+impl fmt::Display for LaunchPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.wrapper.is_empty() {
+            writeln!(f, "Wrapper:")?;
+            for arg in &self.wrapper {
+                writeln!(f, "  {:<60} # {}", arg.value, arg.source)?;
+            }
+        }
+        writeln!(f, "Executable:")?;
+        writeln!(f, "  {}", self.executable)?;
+        writeln!(f, "Arguments:")?;
+        for arg in &self.args {
+            writeln!(f, "  {:<60} # {}", arg.value, arg.source)?;
+        }
+        if !self.env.is_empty() {
+            writeln!(f, "Environment:")?;
+            for var in &self.env {
+                writeln!(f, "  {:<60} # {}", format!("{}={}", var.name, var.value), var.source)?;
+            }
+        }
+        if !self.profile_actions.is_empty() {
+            writeln!(f, "Profile actions:")?;
+            for action in &self.profile_actions {
+                writeln!(f, "  {}", action)?;
+            }
+        }
+        if let Some((width, height)) = self.virtual_display {
+            writeln!(f, "Virtual display:")?;
+            writeln!(f, "  Xvfb {}x{}x24, DISPLAY set to the first free display from :99", width, height)?;
+        }
+        if let Some(ref limits) = self.resource_limits {
+            writeln!(f, "Resource limits:")?;
+            writeln!(f, "  child cgroup of the current cgroup v2: {}", limits)?;
+        }
+        if !self.process_controls.is_empty() {
+            writeln!(f, "Process controls:")?;
+            writeln!(f, "  {}", self.process_controls)?;
+        }
+        if let Some((port, ref upstream)) = self.proxy_forwarder {
+            writeln!(f, "Proxy forwarder:")?;
+            let username = upstream.credentials.as_ref().map(|credentials| credentials.username.as_str());
+            let listen = match port {
+                0 => "127.0.0.1:<free port>".to_string(),
+                port => format!("127.0.0.1:{}", port),
+            };
+            writeln!(
+                f,
+                "  {} -> {} with Proxy-Authorization for {}",
+                listen,
+                upstream,
+                username.unwrap_or_default()
+            )?;
+        }
+        if !self.extensions.is_empty() {
+            writeln!(f, "Extensions:")?;
+            for extension in &self.extensions {
+                writeln!(f, "  {}", extension)?;
+            }
+        }
+        if !self.native_messaging_hosts.is_empty() {
+            writeln!(f, "Native messaging hosts:")?;
+            for (path, manifest) in &self.native_messaging_hosts {
+                writeln!(f, "  write {} (removed on shutdown): {}", path.display(), manifest)?;
+            }
+        }
+        if !self.deferred_urls.is_empty() {
+            writeln!(f, "Opened over DevTools once ready:")?;
+            for url in &self.deferred_urls {
+                writeln!(f, "  {}", url)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,442 @@
+//! Structured proxy configuration and its browser flags and preferences
+
+use serde_json::{json, Value};
+use std::fmt;
+use std::str::FromStr;
+
+/// URL schemes a per-scheme proxy rule can apply to
+const RULE_SCHEMES: &[&str] = &["http", "https", "ftp"];
+
+/// Protocol spoken to a proxy server
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProxyScheme {
+    Http,
+    Https,
+    Socks4,
+    Socks5,
+}
+
+impl ProxyScheme {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProxyScheme::Http => "http",
+            ProxyScheme::Https => "https",
+            ProxyScheme::Socks4 => "socks4",
+            ProxyScheme::Socks5 => "socks5",
+        }
+    }
+
+    /// Port used when a server does not name one
+    pub fn default_port(&self) -> u16 {
+        match self {
+            ProxyScheme::Http => 80,
+            ProxyScheme::Https => 443,
+            ProxyScheme::Socks4 | ProxyScheme::Socks5 => 1080,
+        }
+    }
+
+    fn is_socks(&self) -> bool {
+        matches!(self, ProxyScheme::Socks4 | ProxyScheme::Socks5)
+    }
+}
+
+impl FromStr for ProxyScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "http" => Ok(ProxyScheme::Http),
+            "https" => Ok(ProxyScheme::Https),
+            "socks4" => Ok(ProxyScheme::Socks4),
+            "socks5" => Ok(ProxyScheme::Socks5),
+            other => Err(format!("Unsupported proxy scheme '{}' (expected http, https, socks4 or socks5)", other)),
+        }
+    }
+}
+
+/// Username and password for an upstream proxy
+#[derive(Clone, PartialEq)]
+pub struct ProxyCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl fmt::Debug for ProxyCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyCredentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// One proxy server, `scheme://[user:password@]host:port`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProxyServer {
+    pub scheme: ProxyScheme,
+    pub host: String,
+    pub port: u16,
+    /// Sent as `Proxy-Authorization` by a local forwarder, since Chromium
+    /// ignores credentials in `--proxy-server`; http proxies only
+    pub credentials: Option<ProxyCredentials>,
+}
+
+impl FromStr for ProxyServer {
+    type Err = String;
+
+    /// Parse `[scheme://][user:password@]host[:port]`; the scheme defaults to
+    /// http, and credentials may be percent-encoded
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim();
+        let (scheme, rest) = match value.split_once("://") {
+            Some((scheme, rest)) => (scheme.parse::<ProxyScheme>()?, rest),
+            None => (ProxyScheme::Http, value),
+        };
+        let rest = rest.strip_suffix('/').unwrap_or(rest);
+        // Passwords may contain '@', the host may not
+        let (credentials, authority) = match rest.rsplit_once('@') {
+            Some((userinfo, authority)) => {
+                let (username, password) = userinfo.split_once(':').unwrap_or((userinfo, ""));
+                let credentials = ProxyCredentials {
+                    username: percent_decode(username)?,
+                    password: percent_decode(password)?,
+                };
+                (Some(credentials), authority)
+            }
+            None => (None, rest),
+        };
+        // Error messages must not show the password
+        let value = redact(value);
+        if authority.contains('/') {
+            return Err(format!("Proxy '{}' must not have a path", value));
+        }
+
+        // A bracketed IPv6 host may contain colons
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                let port = port
+                    .parse::<u16>()
+                    .ok()
+                    .filter(|&port| port != 0)
+                    .ok_or_else(|| format!("Invalid port in proxy '{}'", value))?;
+                (host, port)
+            }
+            _ => (authority, scheme.default_port()),
+        };
+        let server = ProxyServer {
+            scheme,
+            host: host.to_string(),
+            port,
+            credentials,
+        };
+        server.validate()?;
+        Ok(server)
+    }
+}
+
+impl fmt::Display for ProxyServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}:{}", self.scheme.name(), self.host, self.port)
+    }
+}
+
+impl ProxyServer {
+    /// `host:port` with the brackets of an IPv6 host removed, for connecting
+    pub fn address(&self) -> (String, u16) {
+        let host = self.host.trim_start_matches('[').trim_end_matches(']');
+        (host.to_string(), self.port)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let valid_host = !self.host.is_empty()
+            && (self.host.starts_with('[') && self.host.ends_with(']')
+                || self
+                    .host
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_'));
+        if !valid_host {
+            return Err(format!("Invalid proxy host '{}'", self.host));
+        }
+        if self.port == 0 {
+            return Err(format!("Invalid port 0 for proxy host '{}'", self.host));
+        }
+        if let Some(ref credentials) = self.credentials {
+            if self.scheme != ProxyScheme::Http {
+                return Err(format!(
+                    "Credentials are only supported for http:// proxies, not {}://{}",
+                    self.scheme.name(),
+                    self.host
+                ));
+            }
+            if credentials.username.is_empty() {
+                return Err(format!("Empty username for proxy host '{}'", self.host));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A proxy server, optionally only for URLs of one scheme
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProxyRule {
+    /// `http`, `https` or `ftp`; `None` applies to every scheme without its own rule
+    pub url_scheme: Option<String>,
+    pub server: ProxyServer,
+}
+
+/// How the browser reaches the network
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProxyConfig {
+    /// Connect directly (`--no-proxy-server`)
+    Direct,
+    /// Use the operating system's proxy settings, the browser default
+    System,
+    /// Proxy auto-config script (`--proxy-pac-url`)
+    Pac(String),
+    /// Fixed proxy servers (`--proxy-server`) and hosts that bypass them (`--proxy-bypass-list`)
+    Manual {
+        rules: Vec<ProxyRule>,
+        bypass_list: Vec<String>,
+    },
+}
+
+impl FromStr for ProxyConfig {
+    type Err = String;
+
+    /// Parse `direct`, `system`, `pac:URL` or proxy rules such as
+    /// `socks5://proxy:1080` or `http=proxy:3128;https=proxy:3129`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim();
+        let config = match value {
+            "direct" => ProxyConfig::Direct,
+            "system" => ProxyConfig::System,
+            _ => match value.strip_prefix("pac:") {
+                Some(url) => ProxyConfig::Pac(url.to_string()),
+                None => ProxyConfig::Manual {
+                    rules: parse_rules(value)?,
+                    bypass_list: Vec::new(),
+                },
+            },
+        };
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Parse `--proxy-server` style rules: `[scheme=]server` separated by `;`
+fn parse_rules(value: &str) -> Result<Vec<ProxyRule>, String> {
+    let rules = value
+        .split(';')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| {
+            // "http=host:80" names a URL scheme; "socks5://host" does not
+            let (url_scheme, server) = match rule.split_once('=') {
+                Some((scheme, server)) if !scheme.contains("://") => (Some(scheme.trim().to_ascii_lowercase()), server),
+                _ => (None, rule),
+            };
+            Ok(ProxyRule {
+                url_scheme,
+                server: server.parse()?,
+            })
+        })
+        .collect::<Result<Vec<ProxyRule>, String>>()?;
+    if rules.is_empty() {
+        return Err("Empty proxy configuration".to_string());
+    }
+    Ok(rules)
+}
+
+impl ProxyConfig {
+    /// Set the hosts that bypass the proxy; only valid for manual proxies
+    pub fn with_bypass_list(self, hosts: Vec<String>) -> Result<Self, String> {
+        match self {
+            ProxyConfig::Manual { rules, .. } => {
+                let config = ProxyConfig::Manual {
+                    rules,
+                    bypass_list: hosts,
+                };
+                config.validate()?;
+                Ok(config)
+            }
+            _ => Err("A proxy bypass list needs proxy servers, not direct, system or PAC settings".to_string()),
+        }
+    }
+
+    /// The upstream proxy that needs credentials, if any
+    pub fn authenticated_upstream(&self) -> Option<&ProxyServer> {
+        match self {
+            ProxyConfig::Manual { rules, .. } => rules
+                .iter()
+                .map(|rule| &rule.server)
+                .find(|server| server.credentials.is_some()),
+            _ => None,
+        }
+    }
+
+    /// The same configuration with the authenticated upstream replaced by a
+    /// forwarder on `127.0.0.1:port`
+    pub fn via_forwarder(&self, port: u16) -> ProxyConfig {
+        match self {
+            ProxyConfig::Manual { rules, bypass_list } => ProxyConfig::Manual {
+                rules: rules
+                    .iter()
+                    .map(|rule| match rule.server.credentials {
+                        Some(_) => ProxyRule {
+                            url_scheme: rule.url_scheme.clone(),
+                            server: ProxyServer {
+                                scheme: ProxyScheme::Http,
+                                host: "127.0.0.1".to_string(),
+                                port,
+                                credentials: None,
+                            },
+                        },
+                        None => rule.clone(),
+                    })
+                    .collect(),
+                bypass_list: bypass_list.clone(),
+            },
+            other => other.clone(),
+        }
+    }
+
+    /// Check URLs, schemes and hosts, including in values built without `parse`
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ProxyConfig::Direct | ProxyConfig::System => Ok(()),
+            ProxyConfig::Pac(url) => {
+                let valid = ["http://", "https://", "file://", "data:"]
+                    .iter()
+                    .any(|prefix| url.len() > prefix.len() && url.to_ascii_lowercase().starts_with(prefix));
+                if valid && !url.contains(char::is_whitespace) {
+                    Ok(())
+                } else {
+                    Err(format!("Invalid PAC URL '{}' (expected an http, https, file or data URL)", url))
+                }
+            }
+            ProxyConfig::Manual { rules, bypass_list } => {
+                if rules.is_empty() {
+                    return Err("A manual proxy configuration needs at least one proxy server".to_string());
+                }
+                for rule in rules {
+                    if let Some(ref scheme) = rule.url_scheme {
+                        if !RULE_SCHEMES.contains(&scheme.as_str()) {
+                            return Err(format!(
+                                "Unsupported URL scheme '{}' in proxy rules (expected {})",
+                                scheme,
+                                RULE_SCHEMES.join(", ")
+                            ));
+                        }
+                    }
+                    rule.server.validate()?;
+                    if let Some(upstream) = self.authenticated_upstream() {
+                        if rule.server.credentials.is_some() && rule.server != *upstream {
+                            return Err("Only one authenticated upstream proxy is supported".to_string());
+                        }
+                    }
+                }
+                if let Some(host) = bypass_list
+                    .iter()
+                    .find(|host| host.trim().is_empty() || host.contains(char::is_whitespace) || host.contains(';'))
+                {
+                    return Err(format!("Invalid proxy bypass entry '{}'", host));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Chromium command line switches
+    pub fn chromium_flags(&self) -> Vec<String> {
+        match self {
+            ProxyConfig::Direct => vec!["--no-proxy-server".to_string()],
+            ProxyConfig::System => Vec::new(),
+            ProxyConfig::Pac(url) => vec![format!("--proxy-pac-url={}", url)],
+            ProxyConfig::Manual { rules, bypass_list } => {
+                let rules: Vec<String> = rules
+                    .iter()
+                    .map(|rule| match rule.url_scheme {
+                        Some(ref scheme) => format!("{}={}", scheme, rule.server),
+                        None => rule.server.to_string(),
+                    })
+                    .collect();
+                let mut flags = vec![format!("--proxy-server={}", rules.join(";"))];
+                if !bypass_list.is_empty() {
+                    flags.push(format!("--proxy-bypass-list={}", bypass_list.join(";")));
+                }
+                flags
+            }
+        }
+    }
+
+    /// Firefox `network.proxy.*` preferences
+    pub fn firefox_prefs(&self) -> Vec<(String, Value)> {
+        let mut prefs = Vec::new();
+        let mut set = |key: &str, value: Value| prefs.push((format!("network.proxy.{}", key), value));
+        match self {
+            ProxyConfig::Direct => set("type", json!(0)),
+            ProxyConfig::System => set("type", json!(5)),
+            ProxyConfig::Pac(url) => {
+                set("type", json!(2));
+                set("autoconfig_url", json!(url));
+            }
+            ProxyConfig::Manual { rules, bypass_list } => {
+                set("type", json!(1));
+                for rule in rules {
+                    let server = &rule.server;
+                    let keys: &[&str] = match (server.scheme.is_socks(), rule.url_scheme.as_deref()) {
+                        (true, _) => &["socks"],
+                        (false, None) => &["http", "ssl"],
+                        (false, Some("http")) => &["http"],
+                        (false, Some("https")) => &["ssl"],
+                        (false, Some(_)) => &["ftp"],
+                    };
+                    for key in keys {
+                        set(key, json!(server.host));
+                        set(&format!("{}_port", key), json!(server.port));
+                    }
+                    if server.scheme.is_socks() {
+                        set("socks_version", json!(if server.scheme == ProxyScheme::Socks4 { 4 } else { 5 }));
+                        set("socks_remote_dns", json!(server.scheme == ProxyScheme::Socks5));
+                    }
+                }
+                if !bypass_list.is_empty() {
+                    set("no_proxies_on", json!(bypass_list.join(", ")));
+                }
+            }
+        }
+        prefs
+    }
+}
+
+/// `value` with any `user:password@` part masked, for error messages
+fn redact(value: &str) -> String {
+    match value.rsplit_once('@') {
+        Some((userinfo, host)) => {
+            let scheme = userinfo.split_once("://").map(|(scheme, _)| format!("{}://", scheme)).unwrap_or_default();
+            format!("{}***@{}", scheme, host)
+        }
+        None => value.to_string(),
+    }
+}
+
+/// Decode `%XX` escapes in proxy credentials
+fn percent_decode(value: &str) -> Result<String, String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = value
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or("Invalid percent-encoding in proxy credentials")?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| "Proxy credentials are not valid UTF-8".to_string())
+}
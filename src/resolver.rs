@@ -0,0 +1,200 @@
+//! Typed `--host-resolver-rules`
+
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+
+/// Replacement Chromium uses to make a host fail to resolve
+const NOT_FOUND: &str = "~NOTFOUND";
+
+/// One host resolver rule
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostRule {
+    /// `MAP pattern target[:port]`
+    Map {
+        pattern: String,
+        target: String,
+        port: Option<u16>,
+    },
+    /// `MAP pattern ~NOTFOUND`
+    NotFound { pattern: String },
+    /// `EXCLUDE pattern`, exempting hosts from earlier wildcard rules
+    Exclude { pattern: String },
+}
+
+impl fmt::Display for HostRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostRule::Map {
+                pattern,
+                target,
+                port: Some(port),
+            } => write!(f, "MAP {} {}:{}", pattern, target, port),
+            HostRule::Map { pattern, target, .. } => write!(f, "MAP {} {}", pattern, target),
+            HostRule::NotFound { pattern } => write!(f, "MAP {} {}", pattern, NOT_FOUND),
+            HostRule::Exclude { pattern } => write!(f, "EXCLUDE {}", pattern),
+        }
+    }
+}
+
+/// Builder for `--host-resolver-rules`; every rule is validated as it is
+/// added, and the first matching rule wins
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostResolverRules {
+    rules: Vec<HostRule>,
+}
+
+impl HostResolverRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve hosts matching `pattern` to `target`
+    pub fn map(self, pattern: &str, target: &str) -> Result<Self, String> {
+        self.push(HostRule::Map {
+            pattern: pattern.to_string(),
+            target: target.to_string(),
+            port: None,
+        })
+    }
+
+    /// Send connections for hosts matching `pattern` to `target:port`
+    pub fn map_port(self, pattern: &str, target: &str, port: u16) -> Result<Self, String> {
+        self.push(HostRule::Map {
+            pattern: pattern.to_string(),
+            target: target.to_string(),
+            port: Some(port),
+        })
+    }
+
+    /// Keep resolving hosts matching `pattern` normally
+    pub fn exclude(self, pattern: &str) -> Result<Self, String> {
+        self.push(HostRule::Exclude {
+            pattern: pattern.to_string(),
+        })
+    }
+
+    /// Make hosts matching `pattern` fail to resolve
+    pub fn not_found(self, pattern: &str) -> Result<Self, String> {
+        self.push(HostRule::NotFound {
+            pattern: pattern.to_string(),
+        })
+    }
+
+    pub fn rules(&self) -> &[HostRule] {
+        &self.rules
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    fn push(mut self, rule: HostRule) -> Result<Self, String> {
+        match rule {
+            HostRule::Map {
+                ref pattern,
+                ref target,
+                port,
+            } => {
+                validate_pattern(pattern)?;
+                validate_target(target)?;
+                if port == Some(0) {
+                    return Err(format!("Invalid port 0 for host resolver target '{}'", target));
+                }
+            }
+            HostRule::NotFound { ref pattern } | HostRule::Exclude { ref pattern } => validate_pattern(pattern)?,
+        }
+        self.rules.push(rule);
+        Ok(self)
+    }
+}
+
+impl fmt::Display for HostResolverRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rules: Vec<String> = self.rules.iter().map(HostRule::to_string).collect();
+        write!(f, "{}", rules.join(", "))
+    }
+}
+
+impl FromStr for HostResolverRules {
+    type Err = String;
+
+    /// Parse raw `--host-resolver-rules` syntax, e.g.
+    /// `MAP *.example.com 127.0.0.1, EXCLUDE localhost`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = HostResolverRules::new();
+        for rule in s.split(',').map(str::trim).filter(|rule| !rule.is_empty()) {
+            let words: Vec<&str> = rule.split_whitespace().collect();
+            rules = match words.as_slice() {
+                [keyword, pattern, target] if keyword.eq_ignore_ascii_case("MAP") => {
+                    if *target == NOT_FOUND {
+                        rules.not_found(pattern)?
+                    } else {
+                        match split_port(target)? {
+                            (host, Some(port)) => rules.map_port(pattern, host, port)?,
+                            (host, None) => rules.map(pattern, host)?,
+                        }
+                    }
+                }
+                [keyword, pattern] if keyword.eq_ignore_ascii_case("EXCLUDE") => rules.exclude(pattern)?,
+                _ => {
+                    return Err(format!(
+                        "Invalid host resolver rule '{}' (expected MAP PATTERN TARGET or EXCLUDE PATTERN)",
+                        rule
+                    ))
+                }
+            };
+        }
+        Ok(rules)
+    }
+}
+
+/// Split `host:port` or `[v6]:port`; a bare IPv6 address has no port
+fn split_port(target: &str) -> Result<(&str, Option<u16>), String> {
+    match target.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && (host.ends_with(']') || !host.contains(':')) => {
+            let port = port
+                .parse::<u16>()
+                .map_err(|_| format!("Invalid port in host resolver target '{}'", target))?;
+            Ok((host, Some(port)))
+        }
+        _ => Ok((target, None)),
+    }
+}
+
+/// A hostname pattern, with `*` and `?` wildcards and an optional `:port`
+fn validate_pattern(pattern: &str) -> Result<(), String> {
+    let (host, port) = match pattern.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => (host, Some(port)),
+        _ => (pattern, None),
+    };
+    let valid_host = !host.is_empty()
+        && (is_bracketed_ipv6(host)
+            || host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '*' | '?')));
+    let valid_port = port.is_none_or(|port| port == "*" || port.parse::<u16>().is_ok_and(|port| port != 0));
+    if valid_host && valid_port {
+        Ok(())
+    } else {
+        Err(format!("Invalid host resolver pattern '{}'", pattern))
+    }
+}
+
+/// A concrete hostname or IP address without wildcards; IPv6 in brackets
+fn validate_target(target: &str) -> Result<(), String> {
+    let valid = !target.is_empty()
+        && (is_bracketed_ipv6(target)
+            || target.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_')));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid host resolver target '{}'", target))
+    }
+}
+
+fn is_bracketed_ipv6(host: &str) -> bool {
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .is_some_and(|host| host.parse::<Ipv6Addr>().is_ok())
+}
@@ -0,0 +1,155 @@
+//! Scheduling controls applied to the browser between fork and exec
+
+use std::fmt;
+use std::process::Command;
+
+/// Highest CPU index a Linux `cpu_set_t` can hold
+const MAX_CPU: usize = 1023;
+
+/// Niceness, CPU affinity and OOM score for the browser process
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcessControls {
+    /// Scheduling priority, -20 (highest) to 19 (lowest)
+    pub nice: Option<i32>,
+    /// CPUs the browser may run on
+    pub cpu_affinity: Option<Vec<usize>>,
+    /// `/proc/<pid>/oom_score_adj`, -1000 to 1000
+    pub oom_score_adj: Option<i32>,
+}
+
+impl fmt::Display for ProcessControls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(nice) = self.nice {
+            parts.push(format!("nice={}", nice));
+        }
+        if let Some(ref cpus) = self.cpu_affinity {
+            let cpus: Vec<String> = cpus.iter().map(usize::to_string).collect();
+            parts.push(format!("cpu_affinity={}", cpus.join(",")));
+        }
+        if let Some(score) = self.oom_score_adj {
+            parts.push(format!("oom_score_adj={}", score));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+impl ProcessControls {
+    pub fn is_empty(&self) -> bool {
+        self.nice.is_none() && self.cpu_affinity.is_none() && self.oom_score_adj.is_none()
+    }
+
+    /// Check ranges and platform support
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(nice) = self.nice {
+            if !(-20..=19).contains(&nice) {
+                return Err(format!("nice must be between -20 and 19, got {}", nice));
+            }
+            if !cfg!(unix) {
+                return Err("nice requires a Unix host".to_string());
+            }
+        }
+        if let Some(ref cpus) = self.cpu_affinity {
+            if cpus.is_empty() {
+                return Err("cpu_affinity must list at least one CPU".to_string());
+            }
+            if let Some(cpu) = cpus.iter().find(|&&cpu| cpu > MAX_CPU) {
+                return Err(format!("cpu_affinity CPU {} is out of range (0-{})", cpu, MAX_CPU));
+            }
+            if !cfg!(target_os = "linux") {
+                return Err("cpu_affinity requires Linux".to_string());
+            }
+        }
+        if let Some(score) = self.oom_score_adj {
+            if !(-1000..=1000).contains(&score) {
+                return Err(format!("oom_score_adj must be between -1000 and 1000, got {}", score));
+            }
+            if !cfg!(target_os = "linux") {
+                return Err("oom_score_adj requires Linux".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Install a pre-exec hook on `command` that applies the controls in the child
+    #[cfg(unix)]
+    pub fn install(&self, command: &mut Command) {
+        use std::io;
+        use std::os::unix::process::CommandExt;
+
+        if self.is_empty() {
+            return;
+        }
+
+        // Everything is prepared here, the hook itself only makes system calls
+        let nice = self.nice;
+        #[cfg(target_os = "linux")]
+        let affinity = self.cpu_affinity.as_ref().map(|cpus| {
+            // Safety: cpu_set_t is plain data and all-zero is an empty set
+            let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+            for &cpu in cpus {
+                unsafe { libc::CPU_SET(cpu, &mut set) };
+            }
+            set
+        });
+        #[cfg(target_os = "linux")]
+        let oom_score_adj = self.oom_score_adj.map(|score| score.to_string().into_bytes());
+
+        let hook = move || -> io::Result<()> {
+            unsafe {
+                if let Some(nice) = nice {
+                    if libc::setpriority(libc::PRIO_PROCESS, 0, nice) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                #[cfg(target_os = "linux")]
+                if let Some(ref set) = affinity {
+                    if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), set) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                #[cfg(target_os = "linux")]
+                if let Some(ref value) = oom_score_adj {
+                    let fd = libc::open(c"/proc/self/oom_score_adj".as_ptr(), libc::O_WRONLY);
+                    if fd == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    let written = libc::write(fd, value.as_ptr().cast(), value.len());
+                    libc::close(fd);
+                    if written == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+            }
+            Ok(())
+        };
+        // Safety: the hook only calls async-signal-safe functions on data prepared above
+        unsafe {
+            command.pre_exec(hook);
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn install(&self, _command: &mut Command) {}
+}
+
+/// Parse a CPU list such as `0-3,6`
+pub fn parse_cpu_list(list: &str) -> Result<Vec<usize>, String> {
+    let mut cpus = Vec::new();
+    for part in list.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        let parse = |cpu: &str| cpu.trim().parse::<usize>().map_err(|_| format!("Invalid CPU '{}'", cpu));
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                if start > end {
+                    return Err(format!("Invalid CPU range '{}'", part));
+                }
+                cpus.extend(start..=end);
+            }
+            None => cpus.push(parse(part)?),
+        }
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
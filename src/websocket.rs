@@ -0,0 +1,179 @@
+//! Minimal blocking websocket client (RFC 6455), enough for `ws://` protocol endpoints
+
+use crate::utils::base64_encode;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+/// Largest message accepted from the peer, over all of its fragments
+const MAX_MESSAGE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// An open client websocket connection
+pub struct WebSocket {
+    reader: BufReader<TcpStream>,
+    mask_seed: u32,
+}
+
+impl WebSocket {
+    /// Connect to a `ws://host:port/path` URL and perform the opening handshake
+    pub fn connect(url: &str, timeout: Duration) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("ws://")
+            .ok_or_else(|| format!("Unsupported websocket URL (only ws:// is supported): {}", url))?;
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+
+        let stream = TcpStream::connect(authority).map_err(|e| format!("Failed to connect to {}: {}", url, e))?;
+        stream.set_read_timeout(Some(timeout)).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(timeout)).map_err(|e| e.to_string())?;
+
+        let mask_seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0x9e37_79b9)
+            | 1;
+        let mut socket = Self {
+            reader: BufReader::new(stream),
+            mask_seed,
+        };
+
+        let key_bytes: Vec<u8> = (0..4).flat_map(|_| socket.next_mask()).collect();
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            path,
+            authority,
+            base64_encode(&key_bytes)
+        );
+        socket
+            .reader
+            .get_mut()
+            .write_all(request.as_bytes())
+            .map_err(|e| e.to_string())?;
+
+        let mut status_line = String::new();
+        socket.reader.read_line(&mut status_line).map_err(|e| e.to_string())?;
+        if status_line.split_whitespace().nth(1) != Some("101") {
+            return Err(format!("Websocket handshake with {} failed: {}", url, status_line.trim()));
+        }
+        loop {
+            let mut line = String::new();
+            let read = socket.reader.read_line(&mut line).map_err(|e| e.to_string())?;
+            if read == 0 || line == "\r\n" {
+                break;
+            }
+        }
+        Ok(socket)
+    }
+
+    /// Send a text message
+    pub fn send_text(&mut self, text: &str) -> Result<(), String> {
+        self.write_frame(OPCODE_TEXT, text.as_bytes())
+    }
+
+    /// Read the next text message, answering pings on the way
+    pub fn read_text(&mut self) -> Result<String, String> {
+        let mut message = Vec::new();
+        loop {
+            let (fin, opcode, payload) = self.read_frame()?;
+            match opcode {
+                OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                    if (message.len() + payload.len()) as u64 > MAX_MESSAGE_SIZE {
+                        return Err(format!("Websocket message larger than {} bytes", MAX_MESSAGE_SIZE));
+                    }
+                    message.extend_from_slice(&payload);
+                    if fin {
+                        return String::from_utf8(message).map_err(|e| e.to_string());
+                    }
+                }
+                OPCODE_PING => self.write_frame(OPCODE_PONG, &payload)?,
+                OPCODE_PONG => {}
+                OPCODE_CLOSE => {
+                    let _ = self.write_frame(OPCODE_CLOSE, &payload);
+                    return Err("Websocket closed by the remote end".to_string());
+                }
+                other => return Err(format!("Unexpected websocket opcode {:#x}", other)),
+            }
+        }
+    }
+
+    /// Send a close frame; the connection is dropped afterwards
+    pub fn close(mut self) -> Result<(), String> {
+        self.write_frame(OPCODE_CLOSE, &1000u16.to_be_bytes())
+    }
+
+    fn read_frame(&mut self) -> Result<(bool, u8, Vec<u8>), String> {
+        let mut header = [0u8; 2];
+        self.reader.read_exact(&mut header).map_err(|e| e.to_string())?;
+        let fin = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0f;
+        let masked = header[1] & 0x80 != 0;
+        let len = match header[1] & 0x7f {
+            126 => {
+                let mut bytes = [0u8; 2];
+                self.reader.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+                u16::from_be_bytes(bytes) as u64
+            }
+            127 => {
+                let mut bytes = [0u8; 8];
+                self.reader.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+                u64::from_be_bytes(bytes)
+            }
+            len => len as u64,
+        };
+
+        if len > MAX_MESSAGE_SIZE {
+            return Err(format!("Websocket frame of {} bytes is larger than {} bytes", len, MAX_MESSAGE_SIZE));
+        }
+
+        let mut mask = [0u8; 4];
+        if masked {
+            self.reader.read_exact(&mut mask).map_err(|e| e.to_string())?;
+        }
+        let mut payload = vec![0u8; len as usize];
+        self.reader.read_exact(&mut payload).map_err(|e| e.to_string())?;
+        if masked {
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+        Ok((fin, opcode, payload))
+    }
+
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<(), String> {
+        // Client frames are always sent whole and masked
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            len if len < 126 => frame.push(0x80 | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        let mask = self.next_mask();
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+        self.reader.get_mut().write_all(&frame).map_err(|e| e.to_string())
+    }
+
+    /// xorshift32; masking keys only need to be unpredictable to intermediaries
+    fn next_mask(&mut self) -> [u8; 4] {
+        let mut x = self.mask_seed;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.mask_seed = x;
+        x.to_be_bytes()
+    }
+}
@@ -0,0 +1,247 @@
+//! Commands that wrap the browser (sandboxes, prefixes) and finding the real
+//! browser process underneath them
+
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+const PID_POLLS: u32 = 100;
+const PID_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// bubblewrap settings; everything but the profile and downloads directories is read-only
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bwrap {
+    /// bwrap executable; looked up on `PATH` when unset
+    pub executable: Option<String>,
+    /// Writable downloads directory; defaults to `Downloads` inside the profile
+    pub downloads_dir: Option<String>,
+    /// Give the browser its own empty network namespace. The DevTools port is
+    /// then unreachable from outside the sandbox.
+    pub isolate_network: bool,
+    /// Extra bwrap arguments, added after the generated ones
+    pub extra_args: Vec<String>,
+}
+
+/// A sandbox the browser is launched inside of
+#[derive(Debug, Clone, PartialEq)]
+pub enum SandboxWrapper {
+    /// bubblewrap with generated bind mounts
+    Bwrap(Bwrap),
+    /// Any other command, such as `firejail --private`, put before the browser
+    Prefix(Vec<String>),
+}
+
+impl FromStr for SandboxWrapper {
+    type Err = String;
+
+    /// Parse `bwrap`, `bwrap:no-network,downloads=/path` or a prefix command
+    /// such as `firejail --private`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim();
+        let bwrap_options = match value {
+            "bwrap" => Some(""),
+            _ => value.strip_prefix("bwrap:"),
+        };
+        if let Some(options) = bwrap_options {
+            let mut bwrap = Bwrap::default();
+            for option in options.split(',').map(str::trim).filter(|option| !option.is_empty()) {
+                match option.split_once('=') {
+                    None if option == "no-network" => bwrap.isolate_network = true,
+                    Some(("downloads", dir)) => bwrap.downloads_dir = Some(dir.to_string()),
+                    Some(("path", path)) => bwrap.executable = Some(path.to_string()),
+                    _ => return Err(format!("Unknown bwrap option '{}' (expected no-network, downloads=DIR or path=BWRAP)", option)),
+                }
+            }
+            return Ok(SandboxWrapper::Bwrap(bwrap));
+        }
+
+        let prefix: Vec<String> = value.split_whitespace().map(String::from).collect();
+        if prefix.is_empty() {
+            return Err("Empty sandbox wrapper".to_string());
+        }
+        Ok(SandboxWrapper::Prefix(prefix))
+    }
+}
+
+impl SandboxWrapper {
+    /// The downloads directory the browser should use, if the wrapper has one
+    pub fn downloads_dir(&self, profile_dir: &Path) -> Option<PathBuf> {
+        match self {
+            SandboxWrapper::Bwrap(bwrap) => Some(
+                bwrap
+                    .downloads_dir
+                    .as_ref()
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| profile_dir.join("Downloads")),
+            ),
+            SandboxWrapper::Prefix(_) => None,
+        }
+    }
+
+    /// The command line put before the browser executable
+    pub fn command_prefix(&self, profile_dir: &Path, browser_executable: &str) -> Result<Vec<String>, String> {
+        match self {
+            SandboxWrapper::Prefix(prefix) => Ok(prefix.clone()),
+            SandboxWrapper::Bwrap(bwrap) => {
+                if !cfg!(target_os = "linux") {
+                    return Err("The bwrap sandbox wrapper requires Linux".to_string());
+                }
+                let executable = match bwrap.executable {
+                    Some(ref path) => path.clone(),
+                    None => find_on_path("bwrap")
+                        .ok_or("sandbox_wrapper is bwrap but bwrap was not found on PATH; install bubblewrap")?
+                        .to_string_lossy()
+                        .to_string(),
+                };
+                let downloads_dir = self.downloads_dir(profile_dir).unwrap_or_default();
+                Ok(bwrap_args(
+                    executable,
+                    bwrap,
+                    profile_dir,
+                    &downloads_dir,
+                    Path::new(browser_executable),
+                    env::var_os("HOME").map(PathBuf::from).as_deref(),
+                ))
+            }
+        }
+    }
+}
+
+/// The bwrap command line: a read-only root with private /dev, /proc, /tmp and
+/// home, and writable binds for the profile and downloads directories
+pub fn bwrap_args(
+    executable: String,
+    bwrap: &Bwrap,
+    profile_dir: &Path,
+    downloads_dir: &Path,
+    browser_executable: &Path,
+    home: Option<&Path>,
+) -> Vec<String> {
+    let path = |path: &Path| path.to_string_lossy().to_string();
+    let mut args = vec![executable];
+    args.extend(["--die-with-parent", "--new-session", "--unshare-all"].map(String::from));
+    if !bwrap.isolate_network {
+        args.push("--share-net".to_string());
+    }
+    args.extend(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"].map(String::from));
+
+    // Keep the X server reachable for headful and virtual display runs
+    if Path::new("/tmp/.X11-unix").exists() {
+        args.extend(["--ro-bind", "/tmp/.X11-unix", "/tmp/.X11-unix"].map(String::from));
+    }
+
+    if let Some(home) = home {
+        args.extend(["--tmpfs".to_string(), path(home)]);
+        // Browsers installed under the home directory must stay visible
+        if let Some(install_dir) = browser_executable.parent().filter(|dir| dir.starts_with(home)) {
+            args.extend(["--ro-bind".to_string(), path(install_dir), path(install_dir)]);
+        }
+    }
+
+    for dir in [profile_dir, downloads_dir] {
+        args.extend(["--bind".to_string(), path(dir), path(dir)]);
+    }
+    args.extend(["--chdir".to_string(), path(profile_dir)]);
+    args.extend(bwrap.extra_args.iter().cloned());
+    args
+}
+
+fn find_on_path(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Wait for the browser to appear under the wrapper process `wrapper` and
+/// return its PID.
+///
+/// `None` if the browser cannot be found in time; fails if the wrapper exits
+/// first. Outside Linux this is the wrapper's own PID.
+pub fn wait_for_browser_pid(wrapper: &mut Child, executable: &str) -> Result<Option<u32>, String> {
+    if !cfg!(target_os = "linux") {
+        return Ok(Some(wrapper.id()));
+    }
+    for _ in 0..PID_POLLS {
+        if let Some(pid) = find_browser_pid(wrapper.id(), executable) {
+            return Ok(Some(pid));
+        }
+        if let Ok(Some(status)) = wrapper.try_wait() {
+            return Err(format!("The wrapper command exited ({}) before starting {}", status, executable));
+        }
+        thread::sleep(PID_POLL_INTERVAL);
+    }
+    Ok(None)
+}
+
+/// Find the browser among `root_pid` and its descendants, nearest first.
+///
+/// A process matches when it runs `executable`, or when `executable` is a
+/// script and the process is its interpreter running it. Wrappers that only
+/// take the browser as an argument, like `strace`, never match. Always `None`
+/// outside Linux.
+pub fn find_browser_pid(root_pid: u32, executable: &str) -> Option<u32> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let processes = list_processes();
+    let target_name = Path::new(executable).file_name();
+    let interpreters = shebang_interpreters(executable);
+
+    let mut queue = vec![root_pid];
+    let mut index = 0;
+    while index < queue.len() {
+        let pid = queue[index];
+        index += 1;
+
+        let argv = fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+        let mut args = argv.split(|&b| b == 0).map(|arg| String::from_utf8_lossy(arg).to_string());
+        let argv0 = args.next().unwrap_or_default();
+        let argv1 = args.next().unwrap_or_default();
+        let argv0_name = Path::new(&argv0).file_name();
+        let runs_script = argv1 == executable && argv0_name.is_some_and(|name| interpreters.iter().any(|i| i == name));
+        if argv0 == executable || runs_script || (!argv0.is_empty() && argv0_name == target_name) {
+            return Some(pid);
+        }
+        queue.extend(processes.iter().filter(|(_, ppid)| *ppid == pid).map(|(pid, _)| *pid));
+    }
+    None
+}
+
+/// File names on the `#!` line of `executable`, e.g. `["env", "bash"]`; empty
+/// for binaries
+fn shebang_interpreters(executable: &str) -> Vec<OsString> {
+    let mut head = [0u8; 256];
+    let len = File::open(executable)
+        .and_then(|mut file| file.read(&mut head))
+        .unwrap_or(0);
+    let Some(line) = head[..len].strip_prefix(b"#!") else {
+        return Vec::new();
+    };
+    let line = String::from_utf8_lossy(line.split(|&b| b == b'\n').next().unwrap_or_default());
+    line.split_whitespace()
+        .filter_map(|word| Path::new(word).file_name().map(|name| name.to_os_string()))
+        .collect()
+}
+
+/// `(pid, parent pid)` of every process in `/proc`
+fn list_processes() -> Vec<(u32, u32)> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(|pid| {
+            // "pid (comm) state ppid ..."; comm may contain spaces and parentheses
+            let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+            let ppid = stat.rsplit_once(')')?.1.split_whitespace().nth(1)?.parse().ok()?;
+            Some((pid, ppid))
+        })
+        .collect()
+}